@@ -0,0 +1,150 @@
+use crate::evaluator::{Evaluator, MoveFilter};
+use crate::probabilities::Probabilities;
+use bkgm::GameState::{GameOver, Ongoing};
+use bkgm::{Dice, State};
+
+/// Evaluation of one legal move.
+#[derive(Clone, Debug)]
+pub struct MoveAnalysis<G: State> {
+    /// Resulting position as returned by `possible_positions`, seen from the opponent.
+    pub position: G,
+    /// Probabilities from the view of the player making the move.
+    pub probabilities: Probabilities,
+    /// Cubeless equity from the view of the player making the move.
+    pub equity: f32,
+    /// Equity lost compared to the first ranked move.
+    pub equity_loss: f32,
+    /// Name of the evaluator which produced the probabilities, e.g. `2-ply PubEval`.
+    pub evaluator: String,
+}
+
+impl<G: State> MoveAnalysis<G> {
+    fn new(position: G, probabilities: Probabilities, evaluator: String) -> Self {
+        Self {
+            position,
+            probabilities,
+            equity: probabilities.equity(),
+            equity_loss: 0.0,
+            evaluator,
+        }
+    }
+}
+
+/// Evaluates every legal move of `pos` with `dice`, best move first.
+pub fn analyze<G: State, E: Evaluator<G>>(
+    evaluator: &E,
+    pos: &G,
+    dice: &Dice,
+) -> Vec<MoveAnalysis<G>> {
+    let positions = pos.possible_positions(dice);
+    let probabilities = evaluate(evaluator, &positions);
+    let name = evaluator.name();
+    let moves = positions
+        .into_iter()
+        .zip(probabilities)
+        .map(|(position, probs)| MoveAnalysis::new(position, probs, name.clone()))
+        .collect();
+    rank(moves, vec![])
+}
+
+/// Evaluates every legal move of `pos` with `dice` by `base`. The moves surviving `filter` are
+/// evaluated again by the usually more expensive `deep` evaluator.
+///
+/// Moves evaluated by `deep` are ranked first, followed by the others with their evaluation by
+/// `base`, like gnubg does. Equity losses of the latter are therefore only rough estimates.
+pub fn analyze_filtered<G: State, B: Evaluator<G>, E: Evaluator<G>>(
+    base: &B,
+    deep: &E,
+    filter: &MoveFilter,
+    pos: &G,
+    dice: &Dice,
+) -> Vec<MoveAnalysis<G>> {
+    let mut moves = analyze(base, pos, dice);
+    // The filter expects evaluations from the view of the opponent.
+    let values: Vec<f32> = moves.iter().map(|m| -m.equity).collect();
+    let mut survivors = filter.survivors(&values);
+    survivors.sort_unstable();
+
+    let positions: Vec<G> = survivors.iter().map(|&i| moves[i].position).collect();
+    let probabilities = evaluate(deep, &positions);
+    let name = deep.name();
+    let mut deep_moves = Vec::with_capacity(survivors.len());
+    // Remove from the back so that the remaining indices stay valid.
+    for (&i, probs) in survivors.iter().zip(probabilities).rev() {
+        let position = moves.remove(i).position;
+        deep_moves.push(MoveAnalysis::new(position, probs, name.clone()));
+    }
+    rank(deep_moves, moves)
+}
+
+/// Probabilities of `positions` from the view of the player who moved there.
+fn evaluate<G: State, E: Evaluator<G>>(evaluator: &E, positions: &[G]) -> Vec<Probabilities> {
+    let ongoing: Vec<G> = positions
+        .iter()
+        .filter(|pos| matches!(pos.game_state(), Ongoing))
+        .copied()
+        .collect();
+    let mut evaluated = evaluator.eval_batch(&ongoing).into_iter();
+    positions
+        .iter()
+        .map(|pos| match pos.game_state() {
+            GameOver(result) => Probabilities::from_result(&result).flip(),
+            Ongoing => evaluated.next().unwrap().flip(),
+        })
+        .collect()
+}
+
+/// Sorts both groups by equity, puts `first` before `rest` and sets the equity losses.
+fn rank<G: State>(
+    mut first: Vec<MoveAnalysis<G>>,
+    mut rest: Vec<MoveAnalysis<G>>,
+) -> Vec<MoveAnalysis<G>> {
+    first.sort_by(|a, b| b.equity.partial_cmp(&a.equity).unwrap());
+    rest.sort_by(|a, b| b.equity.partial_cmp(&a.equity).unwrap());
+    first.append(&mut rest);
+    if let Some(best) = first.first().map(|m| m.equity) {
+        for m in first.iter_mut() {
+            m.equity_loss = best - m.equity;
+        }
+    }
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{analyze, analyze_filtered};
+    use crate::evaluator::{MoveFilter, PartialEvaluator, PlyEvaluator, PubEval};
+    use bkgm::{Backgammon, Dice, State};
+
+    #[test]
+    fn moves_are_ranked() {
+        let pubeval = PubEval::new();
+        let pos = Backgammon::new();
+        let dice = Dice::new(5, 4);
+
+        let moves = analyze(&pubeval, &pos, &dice);
+        assert_eq!(moves.len(), pos.possible_positions(&dice).len());
+        assert!(moves[0].position == pubeval.best_position(&pos, &dice));
+        assert_eq!(moves[0].equity_loss, 0.0);
+        assert!(moves.windows(2).all(|w| w[0].equity >= w[1].equity));
+        assert!(moves.iter().all(|m| m.evaluator == "PubEval"));
+    }
+
+    #[test]
+    fn survivors_are_evaluated_deeper() {
+        let pubeval = PubEval::new();
+        let ply = PlyEvaluator::new(PubEval::new(), 1);
+        let pos = Backgammon::new();
+        let dice = Dice::new(5, 4);
+
+        let moves = analyze_filtered(&pubeval, &ply, &MoveFilter::TINY, &pos, &dice);
+        assert_eq!(moves.len(), pos.possible_positions(&dice).len());
+        let deep = moves
+            .iter()
+            .take_while(|m| m.evaluator == "1-ply PubEval")
+            .count();
+        assert!(deep >= MoveFilter::TINY.accept);
+        assert!(moves[deep..].iter().all(|m| m.evaluator == "PubEval"));
+        assert!(moves[..deep].windows(2).all(|w| w[0].equity >= w[1].equity));
+    }
+}
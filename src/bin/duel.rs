@@ -1,51 +1,52 @@
-use bkgm::{Backgammon, Hypergammon, State};
-use clap::Parser;
+use bkgm::{Backgammon, Hypergammon};
+use clap::{Parser, ValueEnum};
 use staffa::dice::FastrandDice;
 use staffa::duel::Duel;
-use staffa::evaluator::{
-    HyperEvaluator, NNEvaluator, OnnxEvaluator, PartialEvaluator, PubEval, RandomEvaluator,
-    RolloutEvaluator, WildbgEvaluator,
-};
+use staffa::evaluator::{EvaluatorSpec, InstrumentedEvaluator, SpecGame};
 use staffa::probabilities::{Probabilities, ResultCounter};
-use std::{
-    io::{stdout, Write},
-    path::PathBuf,
-};
+use std::io::{self, stdout, Write};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Game {
+    Backgammon,
+    Hypergammon,
+}
 
-// TODO: improve argument names & allow for rollouts, random, etc.
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Model 1
-    model1: PathBuf,
+    /// Evaluator 1, e.g. pubeval, onnx:model/staffa.onnx, ply(1,wildbg) or star(2,wildbg)
+    evaluator1: EvaluatorSpec,
 
-    /// Model 2
-    model2: PathBuf,
+    /// Evaluator 2
+    evaluator2: EvaluatorSpec,
 
     /// Matches
     #[arg(short = 'm', long = "matches", default_value = "10000")]
     matches: usize,
+
+    /// Game
+    #[arg(short = 'g', long = "game", value_enum, default_value = "backgammon")]
+    game: Game,
+
+    /// Print evaluation statistics of both evaluators at the end
+    #[arg(long = "stats", default_value = "false")]
+    stats: bool,
 }
 
-fn run(args: &Args) {
-    let evaluator1 = HyperEvaluator::new().unwrap();
-    // let evaluator1 =
-    // WildbgEvaluator::<Backgammon>::from_file_path(&args.model1).expect("Model not found");
-    // let evaluator1 = PubEval::<Backgammon>::new();
-    let evaluator2 = WildbgEvaluator::from_file_path(&args.model2).expect("Model not found");
-    // let evaluator2 = RolloutEvaluator::new_random();
-    // let evaluator2 = RandomEvaluator::new();
-    duel(evaluator1, evaluator2, args.matches);
+fn run(args: &Args) -> io::Result<()> {
+    match args.game {
+        Game::Backgammon => duel::<Backgammon>(args),
+        Game::Hypergammon => duel::<Hypergammon>(args),
+    }
 }
 
-fn duel<G: State>(
-    evaluator1: impl PartialEvaluator<G>,
-    evaluator2: impl PartialEvaluator<G>,
-    rounds: usize,
-) {
+fn duel<G: SpecGame>(args: &Args) -> io::Result<()> {
+    let evaluator1 = InstrumentedEvaluator::new(args.evaluator1.build_partial::<G>()?);
+    let evaluator2 = InstrumentedEvaluator::new(args.evaluator2.build_partial::<G>()?);
     let duel = Duel::new(evaluator1, evaluator2);
     let mut results = ResultCounter::default();
-    for _ in 0..rounds {
+    for _ in 0..args.matches {
         let outcome = duel.duel(&mut FastrandDice::new());
         results = results.combine(&outcome);
         let probabilities = Probabilities::from(&results);
@@ -59,9 +60,14 @@ fn duel<G: State>(
         stdout().flush().unwrap()
     }
     println!("\nDone");
+    if args.stats {
+        println!("{}", duel.evaluator1().stats());
+        println!("{}", duel.evaluator2().stats());
+    }
+    Ok(())
 }
 
-fn main() {
+fn main() -> io::Result<()> {
     let args = Args::parse();
-    run(&args);
+    run(&args)
 }
@@ -31,8 +31,7 @@ fn count_lines<R: io::Read>(reader: R) -> io::Result<usize> {
 }
 
 fn run(args: &Args) -> io::Result<()> {
-    let evaluator =
-        WildbgEvaluator::<Backgammon>::from_file_path(&args.model).expect("Model not found");
+    let evaluator = WildbgEvaluator::<Backgammon>::from_file_path(&args.model)?;
 
     let mut infile = File::open(&args.infile)?;
     let outfile = File::create(&args.outfile)?;
@@ -0,0 +1,74 @@
+use bkgm::Backgammon;
+use clap::Parser;
+use staffa::dice::FastrandDice;
+use staffa::duel::Duel;
+use staffa::evaluator::{DynPartialEvaluator, EvaluatorSpec};
+use staffa::met::{MatchEquityTable, MetParameters, DEFAULT_MET_LENGTH};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Make match equity table
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Output file
+    #[arg(short = 'f', long = "file", default_value = "data/met.txt")]
+    file: PathBuf,
+
+    /// Longest match in the table
+    #[arg(short = 'l', long = "length", default_value_t = DEFAULT_MET_LENGTH)]
+    length: usize,
+
+    /// Share of won games which are gammons
+    #[arg(short = 'g', long = "gammon-rate", default_value = "0.25")]
+    gammon_rate: f32,
+
+    /// Share of pre-Crawford games played for a doubled cube
+    #[arg(short = 'd', long = "double-rate", default_value = "0.5")]
+    double_rate: f32,
+
+    /// Value of the free drop when the trailer is 2-away post-Crawford
+    #[arg(long = "free-drop-2", default_value = "0.015")]
+    free_drop_2: f32,
+
+    /// Value of the free drop when the trailer is 4-away post-Crawford
+    #[arg(long = "free-drop-4", default_value = "0.004")]
+    free_drop_4: f32,
+
+    /// Measure the gammon rate with this evaluator playing against itself, e.g. wildbg
+    #[arg(short = 'e', long = "evaluator")]
+    evaluator: Option<EvaluatorSpec>,
+
+    /// Duels to measure the gammon rate
+    #[arg(short = 'n', long = "duels", default_value = "1000")]
+    duels: usize,
+}
+
+fn run(args: &Args) -> io::Result<()> {
+    let mut params = MetParameters {
+        gammon_rate: args.gammon_rate,
+        double_rate: args.double_rate,
+        free_drop_2: args.free_drop_2,
+        free_drop_4: args.free_drop_4,
+    };
+    if let Some(spec) = &args.evaluator {
+        let evaluator: Arc<dyn DynPartialEvaluator<Backgammon>> =
+            spec.build_partial::<Backgammon>()?.into();
+        let duel = Duel::new(evaluator.clone(), evaluator);
+        let measured = MetParameters::from_duel(&duel, args.duels, &mut FastrandDice::new());
+        println!("Gammon rate: {:.1}%", measured.gammon_rate * 100.0);
+        params = params.with_gammon_rate(measured.gammon_rate);
+    }
+
+    let met = MatchEquityTable::generate(args.length, &params);
+    met.write_to_file(&args.file)?;
+    println!("Written to {}", args.file.display());
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    run(&args)
+}
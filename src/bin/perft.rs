@@ -5,6 +5,12 @@ use bkgm::{
     State,
 };
 use clap::Parser;
+use staffa::evaluator::{
+    DynPartialEvaluator, EvaluatorSpec, InstrumentedEvaluator, PartialEvaluator,
+};
+use std::io;
+
+type Instrumented = InstrumentedEvaluator<Box<dyn DynPartialEvaluator<Backgammon>>, Backgammon>;
 
 /// Benchmark and test position / move generation
 
@@ -22,18 +28,25 @@ struct Args {
     /// Verbose
     #[arg(short = 'v', long = "verbose", default_value = "false")]
     verbose: bool,
+
+    /// Also choose a move with this evaluator for every roll and print its statistics
+    #[arg(short = 'e', long = "evaluator")]
+    evaluator: Option<EvaluatorSpec>,
 }
 
-fn perft_rec(depth: usize, position: &Backgammon) -> u64 {
+fn perft_rec(depth: usize, position: &Backgammon, evaluator: Option<&Instrumented>) -> u64 {
     if depth == 0 {
         return 1;
     }
     let mut count = 0;
     for (die, _) in ALL_21 {
+        if let Some(evaluator) = evaluator {
+            evaluator.best_position(position, &die);
+        }
         let children = position.possible_positions(&die);
         for child in children {
             count += match child.game_state() {
-                Ongoing => perft_rec(depth - 1, &child),
+                Ongoing => perft_rec(depth - 1, &child, evaluator),
                 GameOver(_) => 1,
             };
         }
@@ -41,18 +54,21 @@ fn perft_rec(depth: usize, position: &Backgammon) -> u64 {
     count
 }
 
-fn perft(args: &Args) -> u64 {
+fn perft(args: &Args, evaluator: Option<&Instrumented>) -> u64 {
     let position = Backgammon::from_id(&args.position).expect("Invalid position");
     if args.verbose {
         position.show();
     }
     let mut total = 0;
     for die in ALL_SINGLES {
+        if let Some(evaluator) = evaluator {
+            evaluator.best_position(&position, &die);
+        }
         let mut count = 0;
         let children = position.possible_positions(&die);
         for child in children {
             count += match child.game_state() {
-                Ongoing => perft_rec(args.depth - 1, &child),
+                Ongoing => perft_rec(args.depth - 1, &child, evaluator),
                 GameOver(_) => 1,
             };
         }
@@ -64,10 +80,18 @@ fn perft(args: &Args) -> u64 {
     total
 }
 
-fn main() {
+fn main() -> io::Result<()> {
     let args = Args::parse();
+    let evaluator = args
+        .evaluator
+        .as_ref()
+        .map(|spec| {
+            spec.build_partial::<Backgammon>()
+                .map(InstrumentedEvaluator::new)
+        })
+        .transpose()?;
     let start = std::time::Instant::now();
-    let total = perft(&args);
+    let total = perft(&args, evaluator.as_ref());
     let dur = start.elapsed();
     let speed = total as f64 / dur.as_secs_f64();
     let avg_time = dur / total as u32;
@@ -76,6 +100,10 @@ fn main() {
         "Elapsed: {:.2?} Speed: {:.2}/s, Avg: {:.2?}",
         dur, speed, avg_time
     );
+    if let Some(evaluator) = evaluator {
+        println!("{}", evaluator.stats());
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -88,8 +116,9 @@ mod tests {
             depth: 1,
             position: "4HPwATDgc/ABMA".to_string(),
             verbose: false,
+            evaluator: None,
         };
-        let total = perft(&args);
+        let total = perft(&args, None);
         assert_eq!(total, 190);
     }
 }
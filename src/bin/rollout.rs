@@ -1,11 +1,20 @@
 use bkgm::{Backgammon, State};
 use clap::Parser;
 use indicatif::{ProgressBar, ProgressStyle};
-use staffa::evaluator::{Evaluator, NNEvaluator, RolloutEvaluator, WildbgEvaluator};
+use staffa::evaluator::{
+    DynPartialEvaluator, EvaluatorSpec, InstrumentedEvaluator, RolloutEvaluator, RolloutResult,
+    RolloutSettings,
+};
+use staffa::policy::Softmax;
 use staffa::position_finder::PositionFinder;
 use std::fs::File;
 use std::io;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Number of positions rolled out at once
+const BATCH_SIZE: usize = 16;
 
 /// Generate positions and evaluate for training
 
@@ -15,28 +24,89 @@ struct Args {
     /// Output file
     outfile: PathBuf,
 
-    /// Model file
-    #[arg(short = 'm', long = "model", default_value = "model/staffa.onnx")]
-    model: PathBuf,
+    /// Evaluator, e.g. pubeval, onnx:model/staffa.onnx or ply(1,wildbg)
+    #[arg(
+        short = 'e',
+        long = "evaluator",
+        default_value = "wildbg:model/staffa.onnx"
+    )]
+    evaluator: EvaluatorSpec,
 
     /// Number of games to generate
     #[arg(short = 'n', long = "num-positions", default_value = "1000")]
     num_positions: usize,
 
+    /// Temperature of the softmax choosing moves while finding positions, 0 is greedy
+    #[arg(short = 't', long = "temperature", default_value = "0.0")]
+    temperature: f32,
+
+    /// Games per rollout
+    #[arg(long = "trials", default_value = "1296")]
+    trials: NonZeroUsize,
+
+    /// Rolls at the start of the games which are stratified
+    #[arg(long = "stratified", default_value = "2")]
+    stratified: usize,
+
+    /// Rotate the stratified rolls between games, like gnubg's quasi-random dice
+    #[arg(long = "rotate", default_value = "false")]
+    rotate: bool,
+
+    /// Seed of the dice, random if not given
+    #[arg(long = "seed")]
+    seed: Option<u64>,
+
     /// Separator
     #[arg(short = 's', long = "sep", default_value = ",")]
     sep: char, // TODO: Fix this to be a single byte and accept ;
+
+    /// Print evaluation statistics at the end
+    #[arg(long = "stats", default_value = "false")]
+    stats: bool,
 }
 
-fn run(args: &Args) -> io::Result<()> {
-    // add error handling
-    let evaluator =
-        WildbgEvaluator::<Backgammon>::from_file_path(&args.model).expect("Model not found");
+/// Position id, the gnubg outputs and their standard errors
+fn record(position: &Backgammon, result: &RolloutResult) -> Vec<String> {
+    let mut data = vec![position.position_id().to_string()];
+    let probabilities = result.probabilities().to_gnu();
+    let errors = result.gnu_errors();
+    data.extend(
+        probabilities
+            .iter()
+            .chain(&errors)
+            .map(|f| format!("{:.5}", f)),
+    );
+    data
+}
 
-    let headers = vec!["positionid", "win", "wing", "winbg", "lossg", "lossbg"];
+fn run(args: &Args) -> io::Result<()> {
+    let headers = vec![
+        "positionid",
+        "win",
+        "wing",
+        "winbg",
+        "lossg",
+        "lossbg",
+        "winse",
+        "wingse",
+        "winbgse",
+        "lossgse",
+        "lossbgse",
+    ];
 
-    let rollout = RolloutEvaluator::with_evaluator(evaluator.clone());
-    let mut finder = PositionFinder::new(evaluator);
+    let instrumented = Arc::new(InstrumentedEvaluator::new(
+        args.evaluator.build_partial::<Backgammon>()?,
+    ));
+    let evaluator: Arc<dyn DynPartialEvaluator<Backgammon>> = instrumented.clone();
+    let mut settings = RolloutSettings::default()
+        .with_trials(args.trials.get())
+        .with_stratified(args.stratified)
+        .with_rotation(args.rotate);
+    if let Some(seed) = args.seed {
+        settings = settings.with_seed(seed);
+    }
+    let rollout = RolloutEvaluator::with_evaluator(evaluator.clone()).with_settings(settings);
+    let mut finder = PositionFinder::with_policy(Softmax::new(evaluator, args.temperature));
 
     let outfile = File::create(&args.outfile)?;
 
@@ -55,28 +125,27 @@ fn run(args: &Args) -> io::Result<()> {
             .unwrap(),
     );
 
-    let positions = finder.find_positions(args.num_positions);
-    for position in positions.iter() {
-        let probabilities = rollout.eval(position);
-        let mut data = vec![position.position_id().to_string()];
-        data.extend(probabilities.to_gnu().iter().map(|f| format!("{:.5}", f)));
-        wtr.write_record(data).unwrap();
-        let mut data = vec![position.flip().position_id().to_string()];
-        data.extend(
-            probabilities
-                .flip()
-                .to_gnu()
-                .iter()
-                .map(|f| format!("{:.5}", f)),
-        );
-        wtr.write_record(data).unwrap();
-        pb.inc(1);
+    let positions: Vec<Backgammon> = finder
+        .find_positions(args.num_positions)
+        .into_iter()
+        .collect();
+    for batch in positions.chunks(BATCH_SIZE) {
+        let results = rollout.rollout_batch(batch);
+        for (position, result) in batch.iter().zip(results) {
+            wtr.write_record(record(position, &result)).unwrap();
+            wtr.write_record(record(&position.flip(), &result.flip()))
+                .unwrap();
+        }
+        pb.inc(batch.len() as u64);
     }
 
     pb.finish_and_clear();
     let dur = pb.elapsed();
     println!("Positions: {}", args.num_positions);
     println!("Elapsed: {:.2?}", dur);
+    if args.stats {
+        println!("{}", instrumented.stats());
+    }
     Ok(())
 }
 
@@ -0,0 +1,399 @@
+use crate::evaluator::Evaluator;
+use crate::probabilities::Probabilities;
+use bkgm::{GameState, State};
+
+/// Cube efficiency used by gnubg for contact positions.
+pub const DEFAULT_CUBE_EFFICIENCY: f32 = 0.68;
+
+/// Who may double next, seen from the player on roll.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CubeOwner {
+    Centered,
+    Player,
+    Opponent,
+}
+
+/// State of the doubling cube in a money game, seen from the player on roll.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CubeState {
+    pub value: u32,
+    pub owner: CubeOwner,
+    /// Gammons and backgammons only count once the cube has been turned.
+    pub jacoby: bool,
+    /// The taker of a double may immediately redouble and keep the cube.
+    pub beavers: bool,
+}
+
+impl Default for CubeState {
+    fn default() -> Self {
+        Self {
+            value: 1,
+            owner: CubeOwner::Centered,
+            jacoby: false,
+            beavers: false,
+        }
+    }
+}
+
+impl CubeState {
+    pub fn with_jacoby(mut self, jacoby: bool) -> Self {
+        self.jacoby = jacoby;
+        self
+    }
+
+    pub fn with_beavers(mut self, beavers: bool) -> Self {
+        self.beavers = beavers;
+        self
+    }
+
+    /// The same cube seen from the opponent.
+    pub fn flip(&self) -> Self {
+        let owner = match self.owner {
+            CubeOwner::Centered => CubeOwner::Centered,
+            CubeOwner::Player => CubeOwner::Opponent,
+            CubeOwner::Opponent => CubeOwner::Player,
+        };
+        Self { owner, ..*self }
+    }
+
+    /// Whether the player on roll has access to the cube.
+    pub fn may_double(&self) -> bool {
+        self.owner != CubeOwner::Opponent
+    }
+
+    /// The cube after the player on roll doubled and the opponent took.
+    pub fn doubled(&self) -> Self {
+        Self {
+            value: 2 * self.value,
+            owner: CubeOwner::Opponent,
+            ..*self
+        }
+    }
+
+    fn jacoby_applies(&self) -> bool {
+        self.jacoby && self.owner == CubeOwner::Centered
+    }
+
+    /// Cubeless equity, except that gammons count single while the Jacoby rule applies.
+    /// Normalized to the cube value.
+    pub fn dead_cube_equity(&self, probs: &Probabilities) -> f32 {
+        if self.jacoby_applies() {
+            2.0 * probs.win_prob() - 1.0
+        } else {
+            probs.equity()
+        }
+    }
+
+    /// Cubeful money equity of the player on roll, normalized to the cube value.
+    ///
+    /// Uses Rick Janowski's interpolation between the dead cube equity and the equity of a
+    /// perfectly efficient live cube. `cube_efficiency` is the weight of the live cube, gnubg uses
+    /// `DEFAULT_CUBE_EFFICIENCY` for contact positions.
+    pub fn cubeful_equity(&self, probs: &Probabilities, cube_efficiency: f32) -> f32 {
+        let dead = self.dead_cube_equity(probs);
+        let live = self.live_cube_equity(probs);
+        dead * (1.0 - cube_efficiency) + live * cube_efficiency
+    }
+
+    fn live_cube_equity(&self, probs: &Probabilities) -> f32 {
+        let p = probs.win_prob();
+        // Average value of a win and of a loss
+        let w = if p > f32::EPSILON {
+            1.0 + (probs.win_gammon + 2.0 * probs.win_bg) / p
+        } else {
+            1.0
+        };
+        let l = if p < 1.0 - f32::EPSILON {
+            1.0 + (probs.lose_gammon + 2.0 * probs.lose_bg) / (1.0 - p)
+        } else {
+            1.0
+        };
+        // Take point of the opponent and cash point of the player on roll
+        let tp = (l - 0.5) / (w + l + 0.5);
+        let cp = (l + 1.0) / (w + l + 0.5);
+
+        match self.owner {
+            CubeOwner::Centered if self.jacoby => {
+                // Nobody plays on for a gammon that wouldn't count.
+                if p <= tp {
+                    -1.0
+                } else if p >= cp {
+                    1.0
+                } else {
+                    -1.0 + 2.0 * (p - tp) / (cp - tp)
+                }
+            }
+            CubeOwner::Centered => {
+                if p <= tp {
+                    -l + (l - 1.0) * p / tp
+                } else if p < cp {
+                    -1.0 + 2.0 * (p - tp) / (cp - tp)
+                } else {
+                    1.0 + (w - 1.0) * (p - cp) / (1.0 - cp)
+                }
+            }
+            CubeOwner::Player => {
+                if p <= cp {
+                    -l + (l + 1.0) * p / cp
+                } else {
+                    1.0 + (w - 1.0) * (p - cp) / (1.0 - cp)
+                }
+            }
+            CubeOwner::Opponent => {
+                if p <= tp {
+                    -l + (l - 1.0) * p / tp
+                } else {
+                    -1.0 + (w + 1.0) * (p - tp) / (1.0 - tp)
+                }
+            }
+        }
+    }
+}
+
+/// Correct cube action of the player on roll, including the correct response of the opponent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CubeAction {
+    NoDouble,
+    DoubleTake,
+    DoublePass,
+    /// The opponent would pass, but playing on for a gammon is worth more than cashing.
+    TooGood,
+}
+
+/// Cubeful equities of the cube options of the player on roll.
+/// All equities are normalized to the current cube value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CubeDecision {
+    pub action: CubeAction,
+    pub no_double: f32,
+    /// Equity after the opponent took, or beavered if that's better for them.
+    pub double_take: f32,
+    pub double_pass: f32,
+    /// Whether the opponent should beaver instead of a plain take.
+    pub beaver: bool,
+}
+
+impl CubeDecision {
+    /// Returns `None` if the player on roll has no access to the cube.
+    pub fn from_probabilities(
+        probs: &Probabilities,
+        cube: &CubeState,
+        cube_efficiency: f32,
+    ) -> Option<Self> {
+        if !cube.may_double() {
+            return None;
+        }
+        let no_double = cube.cubeful_equity(probs, cube_efficiency);
+        let double_pass = 1.0;
+        let mut double_take = 2.0 * cube.doubled().cubeful_equity(probs, cube_efficiency);
+        let mut beaver = false;
+        if cube.beavers {
+            // After a beaver the cube is on 4 and the opponent, who beavered, keeps it.
+            let beavered = CubeState {
+                value: 4 * cube.value,
+                owner: CubeOwner::Opponent,
+                ..*cube
+            };
+            let double_beaver = 4.0 * beavered.cubeful_equity(probs, cube_efficiency);
+            if double_beaver < double_take {
+                double_take = double_beaver;
+                beaver = true;
+            }
+        }
+
+        let action = if double_take >= double_pass {
+            if no_double > double_pass {
+                CubeAction::TooGood
+            } else {
+                CubeAction::DoublePass
+            }
+        } else if double_take > no_double {
+            CubeAction::DoubleTake
+        } else {
+            CubeAction::NoDouble
+        };
+        Some(Self {
+            action,
+            no_double,
+            double_take,
+            double_pass,
+            beaver,
+        })
+    }
+
+    /// Equity after doubling, given the opponent responds correctly.
+    pub fn double_equity(&self) -> f32 {
+        self.double_take.min(self.double_pass)
+    }
+
+    /// Equity of the correct cube action.
+    pub fn optimal_equity(&self) -> f32 {
+        self.no_double.max(self.double_equity())
+    }
+
+    /// Equity lost by not doubling, zero if not doubling is correct.
+    pub fn no_double_error(&self) -> f32 {
+        self.optimal_equity() - self.no_double
+    }
+
+    /// Equity lost by doubling, zero if doubling is correct.
+    pub fn double_error(&self) -> f32 {
+        self.optimal_equity() - self.double_equity()
+    }
+
+    /// Equity the opponent loses by taking, zero if taking is correct.
+    pub fn take_error(&self) -> f32 {
+        (self.double_take - self.double_pass).max(0.0)
+    }
+
+    /// Equity the opponent loses by passing, zero if passing is correct.
+    pub fn pass_error(&self) -> f32 {
+        (self.double_pass - self.double_take).max(0.0)
+    }
+}
+
+/// Cube decision of the player on roll in `pos`, based on the cubeless probabilities of
+/// `evaluator`. The search depth is the one of the evaluator, e.g. a `PlyEvaluator`.
+/// Returns `None` if the game is over or the player on roll has no access to the cube.
+pub fn cube_decision<G: State, E: Evaluator<G>>(
+    evaluator: &E,
+    pos: &G,
+    cube: &CubeState,
+    cube_efficiency: f32,
+) -> Option<CubeDecision> {
+    match pos.game_state() {
+        GameState::GameOver(_) => None,
+        GameState::Ongoing => {
+            let probs = evaluator.eval(pos);
+            CubeDecision::from_probabilities(&probs, cube, cube_efficiency)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cube::{CubeAction, CubeDecision, CubeOwner, CubeState};
+    use crate::probabilities::Probabilities;
+    use crate::test_utils::assert_close;
+    use bkgm::GameResult;
+
+    fn even_race() -> Probabilities {
+        Probabilities {
+            win_normal: 0.5,
+            lose_normal: 0.5,
+            ..Probabilities::empty()
+        }
+    }
+
+    #[test]
+    fn dead_cube_is_cubeless() {
+        let probs = Probabilities {
+            win_normal: 0.4,
+            win_gammon: 0.1,
+            lose_normal: 0.3,
+            lose_gammon: 0.2,
+            ..Probabilities::empty()
+        };
+        let cube = CubeState::default();
+        assert_close(cube.cubeful_equity(&probs, 0.0), probs.equity());
+        assert_close(cube.with_jacoby(true).cubeful_equity(&probs, 0.0), 0.0);
+    }
+
+    #[test]
+    fn live_cube_even_position() {
+        let probs = even_race();
+        let cube = CubeState::default();
+        let owned = CubeState {
+            owner: CubeOwner::Player,
+            ..cube
+        };
+        assert_close(cube.cubeful_equity(&probs, 1.0), 0.0);
+        assert_close(owned.cubeful_equity(&probs, 1.0), 0.25);
+        assert_close(owned.flip().cubeful_equity(&probs, 1.0), -0.25);
+    }
+
+    #[test]
+    fn certain_win_cashes() {
+        let probs = Probabilities::from_result(&GameResult::WinNormal);
+        for owner in [CubeOwner::Centered, CubeOwner::Player, CubeOwner::Opponent] {
+            let cube = CubeState {
+                owner,
+                ..CubeState::default()
+            };
+            assert_close(cube.cubeful_equity(&probs, 0.68), 1.0);
+        }
+    }
+
+    #[test]
+    fn doubled_cube_belongs_to_opponent() {
+        let cube = CubeState::default().doubled();
+        assert_eq!(cube.value, 2);
+        assert_eq!(cube.owner, CubeOwner::Opponent);
+        assert!(!cube.may_double());
+        assert!(cube.flip().may_double());
+    }
+
+    #[test]
+    fn no_double_in_even_position() {
+        let decision =
+            CubeDecision::from_probabilities(&even_race(), &CubeState::default(), 0.68).unwrap();
+        assert_eq!(decision.action, CubeAction::NoDouble);
+        assert_close(decision.no_double, 0.0);
+        assert_close(decision.double_take, -0.34);
+        assert_close(decision.double_error(), 0.34);
+        assert_close(decision.no_double_error(), 0.0);
+    }
+
+    #[test]
+    fn double_take() {
+        let probs = Probabilities {
+            win_normal: 0.7,
+            lose_normal: 0.3,
+            ..Probabilities::empty()
+        };
+        let decision =
+            CubeDecision::from_probabilities(&probs, &CubeState::default(), 0.68).unwrap();
+        assert_eq!(decision.action, CubeAction::DoubleTake);
+        assert_close(decision.double_take, 0.596);
+        assert_close(decision.take_error(), 0.0);
+        assert_close(decision.pass_error(), 0.404);
+    }
+
+    #[test]
+    fn double_pass_and_too_good() {
+        let cube = CubeState::default();
+        let win = Probabilities::from_result(&GameResult::WinNormal);
+        let decision = CubeDecision::from_probabilities(&win, &cube, 0.68).unwrap();
+        assert_eq!(decision.action, CubeAction::DoublePass);
+
+        let gammon = Probabilities::from_result(&GameResult::WinGammon);
+        let decision = CubeDecision::from_probabilities(&gammon, &cube, 0.68).unwrap();
+        assert_eq!(decision.action, CubeAction::TooGood);
+        assert_close(decision.double_error(), 1.0);
+
+        // With the Jacoby rule a gammon only counts after the cube has been turned.
+        let jacoby = cube.with_jacoby(true);
+        let decision = CubeDecision::from_probabilities(&gammon, &jacoby, 0.68).unwrap();
+        assert_eq!(decision.action, CubeAction::DoublePass);
+    }
+
+    #[test]
+    fn beaver_when_opponent_is_big_favourite() {
+        let probs = Probabilities {
+            win_normal: 0.2,
+            lose_normal: 0.8,
+            ..Probabilities::empty()
+        };
+        let cube = CubeState::default().with_beavers(true);
+        let decision = CubeDecision::from_probabilities(&probs, &cube, 0.68).unwrap();
+        assert!(decision.beaver);
+        assert_eq!(decision.action, CubeAction::NoDouble);
+        assert_close(decision.double_take, -3.488);
+    }
+
+    #[test]
+    fn no_access_to_cube() {
+        let cube = CubeState::default().doubled();
+        assert!(CubeDecision::from_probabilities(&even_race(), &cube, 0.68).is_none());
+    }
+}
@@ -1,4 +1,5 @@
 use bkgm::Dice;
+use std::hash::{Hash, Hasher};
 
 pub trait DiceGen {
     /// Returns dice
@@ -35,6 +36,33 @@ impl FastrandDice {
     }
 }
 
+/// Derives a seed from `seed` and `value`, e.g. a position, so that each value gets its own
+/// random numbers. Unlike `DefaultHasher`, the hash doesn't change between Rust releases.
+pub fn mix_seed<T: Hash>(seed: u64, value: &T) -> u64 {
+    let mut hasher = FnvHasher(FNV_OFFSET_BASIS);
+    seed.hash(&mut hasher);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// 64 bit FNV-1a hash.
+struct FnvHasher(u64);
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
 #[cfg(test)]
 /// Use this for unit tests where you want to control the dice.
 pub(crate) struct DiceGenMock {
@@ -113,6 +141,19 @@ mod fastrand_dice_tests {
     }
 }
 
+#[cfg(test)]
+mod mix_seed_tests {
+    use crate::dice::mix_seed;
+
+    #[test]
+    fn fixed_hash() {
+        // FNV-1a of the eight bytes of the seed, followed by the byte of the value
+        assert_eq!(mix_seed(0, &0u8), 0xe604_823a_2490_29bf);
+        assert_ne!(mix_seed(1, &0u8), mix_seed(0, &0u8));
+        assert_ne!(mix_seed(0, &1u8), mix_seed(0, &0u8));
+    }
+}
+
 #[cfg(test)]
 mod dice_gen_mock_tests {
     use crate::dice::{Dice, DiceGen, DiceGenMock};
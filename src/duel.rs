@@ -23,6 +23,14 @@ impl<T: PartialEvaluator<G>, U: PartialEvaluator<G>, G: State> Duel<T, U, G> {
         }
     }
 
+    pub fn evaluator1(&self) -> &T {
+        &self.evaluator1
+    }
+
+    pub fn evaluator2(&self) -> &U {
+        &self.evaluator2
+    }
+
     /// The two `Evaluator`s will play twice each against each other.
     /// Either `Evaluator` will start once and play with the same dice as vice versa.
     pub fn duel<V: DiceGen>(&self, dice_gen: &mut V) -> ResultCounter {
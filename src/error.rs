@@ -0,0 +1,77 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors when loading evaluators and the data they depend on.
+#[derive(Debug)]
+pub enum Error {
+    /// The given file does not exist.
+    FileNotFound(PathBuf),
+    /// Reading a file failed for any other reason.
+    Io(io::Error),
+    /// The file could not be loaded as an ONNX model.
+    MalformedModel(String),
+    /// The model doesn't take as many inputs as the input encoder produces.
+    InputShape { expected: usize, actual: usize },
+    /// The model doesn't produce as many outputs as expected.
+    OutputShape { expected: usize, actual: usize },
+    /// The database doesn't contain one record for each possible position.
+    TruncatedDatabase { expected: usize, actual: usize },
+    /// The file could not be parsed as a match equity table.
+    MalformedTable(String),
+    /// The evaluator spec can't be parsed or not be built for this game.
+    InvalidSpec(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::FileNotFound(path) => write!(f, "File not found: {}", path.display()),
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::MalformedModel(msg) => write!(f, "Malformed model: {}", msg),
+            Error::InputShape { expected, actual } => {
+                write!(f, "Model takes {} inputs, expected {}", actual, expected)
+            }
+            Error::OutputShape { expected, actual } => write!(
+                f,
+                "Model produces {} outputs, expected {}",
+                actual, expected
+            ),
+            Error::TruncatedDatabase { expected, actual } => write!(
+                f,
+                "Database contains {} records, expected {}",
+                actual, expected
+            ),
+            Error::MalformedTable(msg) => write!(f, "Malformed match equity table: {}", msg),
+            Error::InvalidSpec(msg) => write!(f, "Invalid evaluator spec: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// Lets binaries returning `io::Result` use `?` on loading evaluators.
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            Error::FileNotFound(_) => io::Error::new(io::ErrorKind::NotFound, err.to_string()),
+            err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+        }
+    }
+}
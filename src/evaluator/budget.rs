@@ -0,0 +1,46 @@
+use std::time::Instant;
+
+/// Counts the evaluations of a search and watches its deadline.
+pub(crate) struct Budget {
+    deadline: Option<Instant>,
+    max_evaluations: Option<u64>,
+    evaluations: u64,
+}
+
+impl Budget {
+    pub(crate) fn new(deadline: Option<Instant>, max_evaluations: Option<u64>) -> Self {
+        Self {
+            deadline,
+            max_evaluations,
+            evaluations: 0,
+        }
+    }
+
+    pub(crate) fn unlimited() -> Self {
+        Self::new(None, None)
+    }
+
+    /// Evaluations accounted for so far.
+    pub(crate) fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+
+    /// Accounts for `n` evaluations. Returns false without accounting for them if they would
+    /// exceed the budget or if the deadline has passed.
+    pub(crate) fn spend(&mut self, n: usize) -> bool {
+        let evaluations = self.evaluations + n as u64;
+        if matches!(self.max_evaluations, Some(max) if evaluations > max) {
+            return false;
+        }
+        if matches!(self.deadline, Some(deadline) if Instant::now() >= deadline) {
+            return false;
+        }
+        self.evaluations = evaluations;
+        true
+    }
+
+    /// Accounts for `n` evaluations made outside of the budget, even if they exceed it.
+    pub(crate) fn record(&mut self, n: u64) {
+        self.evaluations += n;
+    }
+}
@@ -0,0 +1,216 @@
+use crate::evaluator::{equity_batch, Evaluator, PartialEvaluator};
+use crate::probabilities::Probabilities;
+use bkgm::State;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Number of hits and misses of a `CachedEvaluator`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    pub fn lookups(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    pub fn hit_rate(&self) -> f32 {
+        match self.lookups() {
+            0 => 0.0,
+            lookups => self.hits as f32 / lookups as f32,
+        }
+    }
+}
+
+/// Two entries sharing a hash slot, the most recently used one comes first.
+type Bucket<G> = [Option<(G, Probabilities)>; 2];
+
+/// Remembers the evaluations of another `Evaluator` in a fixed-size transposition table.
+///
+/// The table is split into two-way buckets, each behind its own lock, so it can be shared by
+/// the threads of a rollout. A new entry evicts the least recently used entry of its bucket.
+pub struct CachedEvaluator<E: Evaluator<G>, G: State + Hash + Eq> {
+    evaluator: E,
+    buckets: Vec<Mutex<Bucket<G>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    phantom: PhantomData<G>,
+}
+
+impl<E: Evaluator<G>, G: State + Hash + Eq> PartialEvaluator<G> for CachedEvaluator<E, G> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        let probs = self.eval(pos);
+        probs.equity()
+    }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        equity_batch(self, positions)
+    }
+}
+
+impl<E: Evaluator<G>, G: State + Hash + Eq> Evaluator<G> for CachedEvaluator<E, G> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        match self.lookup(pos) {
+            Some(probs) => probs,
+            None => {
+                let probs = self.evaluator.eval(pos);
+                self.store(pos, probs);
+                probs
+            }
+        }
+    }
+
+    /// Only the positions missing from the table are passed on, as a single batch.
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        let found: Vec<Option<Probabilities>> =
+            positions.iter().map(|pos| self.lookup(pos)).collect();
+        let missing: Vec<G> = positions
+            .iter()
+            .zip(&found)
+            .filter(|(_, probs)| probs.is_none())
+            .map(|(pos, _)| *pos)
+            .collect();
+
+        let mut evaluated = self.evaluator.eval_batch(&missing).into_iter();
+        positions
+            .iter()
+            .zip(found)
+            .map(|(pos, probs)| match probs {
+                Some(probs) => probs,
+                None => {
+                    let probs = evaluated.next().unwrap();
+                    self.store(pos, probs);
+                    probs
+                }
+            })
+            .collect()
+    }
+}
+
+impl<E: Evaluator<G>, G: State + Hash + Eq> CachedEvaluator<E, G> {
+    /// The table holds at least `capacity` entries, rounded up to a power of two.
+    pub fn new(evaluator: E, capacity: usize) -> Self {
+        let num_buckets = (capacity.max(2) / 2).next_power_of_two();
+        Self {
+            evaluator,
+            buckets: (0..num_buckets).map(|_| Mutex::new([None; 2])).collect(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        2 * self.buckets.len()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Removes all entries and resets the statistics.
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            *bucket.lock().unwrap() = [None; 2];
+        }
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    fn bucket(&self, pos: &G) -> &Mutex<Bucket<G>> {
+        let mut hasher = DefaultHasher::new();
+        pos.hash(&mut hasher);
+        // The number of buckets is a power of two.
+        let index = hasher.finish() as usize & (self.buckets.len() - 1);
+        &self.buckets[index]
+    }
+
+    fn lookup(&self, pos: &G) -> Option<Probabilities> {
+        let mut bucket = self.bucket(pos).lock().unwrap();
+        let found = bucket
+            .iter()
+            .position(|entry| matches!(entry, Some((cached, _)) if cached == pos));
+        match found {
+            Some(index) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                bucket.swap(0, index);
+                bucket[0].map(|(_, probs)| probs)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn store(&self, pos: &G, probs: Probabilities) {
+        let mut bucket = self.bucket(pos).lock().unwrap();
+        if !matches!(bucket[0], Some((cached, _)) if cached == *pos) {
+            bucket[1] = bucket[0];
+        }
+        bucket[0] = Some((*pos, probs));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{CacheStats, CachedEvaluator, Evaluator, OffEvaluator};
+    use bkgm::{bpos, Backgammon};
+
+    #[test]
+    fn second_lookup_is_a_hit() {
+        let cached = CachedEvaluator::new(OffEvaluator, 1024);
+        let pos = bpos!(x 1:3; o 24:1);
+
+        assert_eq!(cached.eval(&pos), OffEvaluator.eval(&pos));
+        assert_eq!(cached.eval(&pos), OffEvaluator.eval(&pos));
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 1 });
+        assert_eq!(cached.stats().hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn batch_only_evaluates_missing_positions() {
+        let cached = CachedEvaluator::new(OffEvaluator, 1024);
+        let first = bpos!(x 1:3; o 24:1);
+        let second = bpos!(x 1:5; o 24:1);
+        cached.eval(&first);
+
+        let results = cached.eval_batch(&[second, first, second]);
+        assert_eq!(results[0], OffEvaluator.eval(&second));
+        assert_eq!(results[1], OffEvaluator.eval(&first));
+        assert_eq!(results[2], OffEvaluator.eval(&second));
+        // The second occurrence of `second` was looked up before the batch was evaluated.
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 3 });
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_replaced() {
+        // A single bucket, all positions compete for the same two entries.
+        let cached = CachedEvaluator::new(OffEvaluator, 2);
+        let positions = [
+            bpos!(x 1:1; o 24:1),
+            bpos!(x 1:2; o 24:1),
+            bpos!(x 1:3; o 24:1),
+        ];
+        cached.eval(&positions[0]);
+        cached.eval(&positions[1]);
+        cached.eval(&positions[0]);
+        cached.eval(&positions[2]); // evicts positions[1]
+        cached.eval(&positions[0]);
+        cached.eval(&positions[1]);
+        assert_eq!(cached.stats(), CacheStats { hits: 2, misses: 4 });
+
+        cached.clear();
+        assert_eq!(cached.stats(), CacheStats::default());
+        cached.eval(&positions[0]);
+        assert_eq!(cached.stats(), CacheStats { hits: 0, misses: 1 });
+    }
+}
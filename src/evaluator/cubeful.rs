@@ -0,0 +1,53 @@
+use crate::cube::{self, CubeDecision, CubeState, DEFAULT_CUBE_EFFICIENCY};
+use crate::evaluator::Evaluator;
+use bkgm::State;
+use std::marker::PhantomData;
+
+pub trait CubefulEvaluator<G: State> {
+    /// Returns the cubeful money equity of a position for the player on roll, normalized to
+    /// the cube value. Multiply by `cube.value` to get points.
+    fn cubeful_equity(&self, pos: &G, cube: &CubeState) -> f32;
+}
+
+/// Lifts any cubeless `Evaluator` into a `CubefulEvaluator` with Janowski's formula.
+pub struct JanowskiEvaluator<E: Evaluator<G>, G: State> {
+    evaluator: E,
+    cube_efficiency: f32,
+    phantom: PhantomData<G>,
+}
+
+impl<E: Evaluator<G>, G: State> CubefulEvaluator<G> for JanowskiEvaluator<E, G> {
+    fn cubeful_equity(&self, pos: &G, cube: &CubeState) -> f32 {
+        let probs = self.evaluator.eval(pos);
+        cube.cubeful_equity(&probs, self.cube_efficiency)
+    }
+}
+
+impl<E: Evaluator<G>, G: State> JanowskiEvaluator<E, G> {
+    pub fn new(evaluator: E) -> Self {
+        Self {
+            evaluator,
+            cube_efficiency: DEFAULT_CUBE_EFFICIENCY,
+            phantom: PhantomData,
+        }
+    }
+
+    /// 0.0 treats the cube as dead, 1.0 as perfectly efficient.
+    pub fn with_cube_efficiency(mut self, cube_efficiency: f32) -> Self {
+        self.cube_efficiency = cube_efficiency;
+        self
+    }
+
+    pub fn cube_efficiency(&self) -> f32 {
+        self.cube_efficiency
+    }
+
+    pub fn evaluator(&self) -> &E {
+        &self.evaluator
+    }
+
+    /// Cube decision of the player on roll, see `cube::cube_decision`.
+    pub fn cube_decision(&self, pos: &G, cube: &CubeState) -> Option<CubeDecision> {
+        cube::cube_decision(&self.evaluator, pos, cube, self.cube_efficiency)
+    }
+}
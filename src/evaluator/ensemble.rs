@@ -0,0 +1,142 @@
+use crate::evaluator::{equity_batch, DynEvaluator, Evaluator, PartialEvaluator};
+use crate::probabilities::Probabilities;
+use bkgm::State;
+
+/// Averages the probabilities of several evaluators, e.g. the nets of one training generation.
+///
+/// The spread of the members' equities is available as an uncertainty estimate, positions with
+/// a high uncertainty are good candidates for further rollouts.
+pub struct EnsembleEvaluator<G: State> {
+    members: Vec<(Box<dyn DynEvaluator<G>>, f32)>,
+}
+
+impl<G: State> PartialEvaluator<G> for EnsembleEvaluator<G> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.eval(pos).equity()
+    }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        equity_batch(self, positions)
+    }
+}
+
+impl<G: State> Evaluator<G> for EnsembleEvaluator<G> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.eval_with_uncertainty(pos).0
+    }
+
+    /// Each member evaluates all positions at once.
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        self.eval_batch_with_uncertainty(positions)
+            .into_iter()
+            .map(|(probs, _)| probs)
+            .collect()
+    }
+}
+
+impl<G: State> EnsembleEvaluator<G> {
+    /// Ensemble of `evaluator` with weight 1. An ensemble always has at least one member, so
+    /// there is always an average to return.
+    pub fn new<E: Evaluator<G> + Send + Sync + 'static>(evaluator: E) -> Self {
+        Self {
+            members: Vec::new(),
+        }
+        .with_member(evaluator)
+    }
+
+    /// Adds a member with weight 1.
+    pub fn with_member<E: Evaluator<G> + Send + Sync + 'static>(self, evaluator: E) -> Self {
+        self.with_weighted_member(evaluator, 1.0)
+    }
+
+    pub fn with_weighted_member<E: Evaluator<G> + Send + Sync + 'static>(
+        self,
+        evaluator: E,
+        weight: f32,
+    ) -> Self {
+        self.with_boxed_member(Box::new(evaluator), weight)
+    }
+
+    /// Adds a member chosen at runtime, e.g. built from an `EvaluatorSpec`.
+    pub fn with_boxed_member(mut self, evaluator: Box<dyn DynEvaluator<G>>, weight: f32) -> Self {
+        assert!(weight > 0.0, "Weights of ensemble members must be positive");
+        self.members.push((evaluator, weight));
+        self
+    }
+
+    pub fn num_members(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Weighted standard deviation of the members' equities.
+    pub fn uncertainty(&self, pos: &G) -> f32 {
+        self.eval_with_uncertainty(pos).1
+    }
+
+    /// Weighted average of the members' probabilities and the standard deviation of their equities.
+    pub fn eval_with_uncertainty(&self, pos: &G) -> (Probabilities, f32) {
+        self.eval_batch_with_uncertainty(std::slice::from_ref(pos))
+            .pop()
+            .unwrap()
+    }
+
+    /// Like `eval_with_uncertainty` for several positions, each member evaluates all at once.
+    pub fn eval_batch_with_uncertainty(&self, positions: &[G]) -> Vec<(Probabilities, f32)> {
+        let evaluations: Vec<Vec<Probabilities>> = self
+            .members
+            .iter()
+            .map(|(evaluator, _)| evaluator.dyn_eval_batch(positions))
+            .collect();
+
+        (0..positions.len())
+            .map(|i| {
+                let weighted: Vec<(Probabilities, f32)> = evaluations
+                    .iter()
+                    .zip(&self.members)
+                    .map(|(probs, (_, weight))| (probs[i], *weight))
+                    .collect();
+                let average = Probabilities::weighted_average(&weighted);
+                let mean = average.equity();
+                let total: f32 = weighted.iter().map(|(_, weight)| weight).sum();
+                let variance = weighted
+                    .iter()
+                    .map(|(probs, weight)| weight * (probs.equity() - mean).powi(2))
+                    .sum::<f32>()
+                    / total;
+                (average, variance.sqrt())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{EnsembleEvaluator, Evaluator, OffEvaluator, PlyEvaluator};
+    use crate::test_utils::assert_close;
+    use bkgm::{bpos, Backgammon};
+
+    #[test]
+    fn identical_members_agree() {
+        let ensemble = EnsembleEvaluator::new(OffEvaluator).with_weighted_member(OffEvaluator, 3.0);
+        let pos = bpos!(x 1:1; o 24:1);
+
+        let (probs, uncertainty) = ensemble.eval_with_uncertainty(&pos);
+        assert_close(probs.win_normal, 14.0 / 15.0);
+        assert_close(uncertainty, 0.0);
+    }
+
+    #[test]
+    fn spread_between_members() {
+        // The 1-ply search sees that every roll wins, the bare evaluator counts borne off checkers.
+        let ensemble = EnsembleEvaluator::<Backgammon>::new(PlyEvaluator::new(OffEvaluator, 1))
+            .with_member(OffEvaluator);
+        let pos = bpos!(x 1:1; o 24:1);
+
+        let (probs, uncertainty) = ensemble.eval_with_uncertainty(&pos);
+        assert_close(probs.win_normal, (1.0 + 14.0 / 15.0) / 2.0);
+        assert_close(probs.equity(), 14.0 / 15.0);
+        assert_close(uncertainty, 1.0 / 15.0);
+        assert_eq!(ensemble.eval_batch(&[pos, pos]).len(), 2);
+        assert_eq!(ensemble.num_members(), 2);
+    }
+}
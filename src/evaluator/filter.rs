@@ -0,0 +1,139 @@
+use super::PartialEvaluator;
+use crate::probabilities::Probabilities;
+use bkgm::{
+    GameState::{GameOver, Ongoing},
+    State,
+};
+
+/// Move filter as used by gnubg to make deeper searches affordable.
+///
+/// All candidates are ranked by a cheap evaluation (usually 0-ply). Only the best `accept` moves
+/// and any further move within `threshold` equity of the best move survive and are passed on to
+/// the more expensive evaluation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveFilter {
+    pub accept: usize,
+    pub threshold: f32,
+}
+
+impl Default for MoveFilter {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
+impl MoveFilter {
+    pub const TINY: MoveFilter = MoveFilter::new(5, 0.08);
+    pub const NARROW: MoveFilter = MoveFilter::new(8, 0.12);
+    pub const NORMAL: MoveFilter = MoveFilter::new(8, 0.16);
+    pub const LARGE: MoveFilter = MoveFilter::new(16, 0.32);
+    pub const HUGE: MoveFilter = MoveFilter::new(20, 0.44);
+
+    pub const fn new(accept: usize, threshold: f32) -> Self {
+        Self { accept, threshold }
+    }
+
+    /// Returns the surviving candidates among `positions`, best first.
+    ///
+    /// `positions` are resulting positions, so they are seen from the opponent and the best move
+    /// is the one with the lowest evaluation. Finished games are scored by their result.
+    pub fn filter<G: State, E: PartialEvaluator<G>>(
+        &self,
+        evaluator: &E,
+        positions: Vec<G>,
+    ) -> Vec<G> {
+        let ongoing: Vec<G> = positions
+            .iter()
+            .filter(|pos| pos.game_state() == Ongoing)
+            .copied()
+            .collect();
+        let mut evaluated = evaluator.try_eval_batch(&ongoing).into_iter();
+        let values: Vec<f32> = positions
+            .iter()
+            .map(|pos| match pos.game_state() {
+                GameOver(result) => Probabilities::from_result(&result).equity(),
+                Ongoing => evaluated.next().unwrap(),
+            })
+            .collect();
+        self.survivors(&values)
+            .into_iter()
+            .map(|i| positions[i])
+            .collect()
+    }
+
+    /// Returns the indices of the surviving candidates, best first, given their evaluations from
+    /// the view of the opponent.
+    pub fn survivors(&self, values: &[f32]) -> Vec<usize> {
+        let mut ranked: Vec<usize> = (0..values.len()).collect();
+        ranked.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap());
+
+        let best = match ranked.first() {
+            Some(&i) => values[i],
+            None => return vec![],
+        };
+        ranked
+            .into_iter()
+            .enumerate()
+            .take_while(|&(rank, i)| {
+                rank == 0 || rank < self.accept || values[i] - best <= self.threshold
+            })
+            .map(|(_, i)| i)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{MoveFilter, PartialEvaluator, PubEval};
+    use bkgm::GameState::{GameOver, Ongoing};
+    use bkgm::{bpos, Backgammon, Dice, State};
+
+    /// Believes that the player who just finished the game lost it.
+    struct Mistaken;
+
+    impl PartialEvaluator<Backgammon> for Mistaken {
+        fn try_eval(&self, pos: &Backgammon) -> f32 {
+            match pos.game_state() {
+                GameOver(_) => 1.0,
+                Ongoing => 0.0,
+            }
+        }
+    }
+
+    #[test]
+    fn accept_one_keeps_best_position() {
+        let pubeval = PubEval::new();
+        let pos = Backgammon::new();
+        let dice = Dice::new(5, 4);
+
+        let kept = MoveFilter::new(1, 0.0).filter(&pubeval, pos.possible_positions(&dice));
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0] == pubeval.best_position(&pos, &dice));
+    }
+
+    #[test]
+    fn large_filter_keeps_everything_best_first() {
+        let pubeval = PubEval::new();
+        let pos = Backgammon::new();
+        let dice = Dice::new(5, 4);
+        let children = pos.possible_positions(&dice);
+
+        let kept = MoveFilter::new(children.len(), 0.0).filter(&pubeval, children.clone());
+        assert_eq!(kept.len(), children.len());
+        assert!(kept[0] == pubeval.best_position(&pos, &dice));
+        let values: Vec<f32> = kept.iter().map(|p| pubeval.try_eval(p)).collect();
+        assert!(values.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn finished_games_are_scored_by_result() {
+        let pos: Backgammon = bpos!(x 6:1, 1:1; o 24:2);
+        let dice = Dice::new(6, 1);
+        let children = pos.possible_positions(&dice);
+        assert!(children.len() > 1);
+
+        // The winning move is the worst one for the evaluator alone.
+        let kept = MoveFilter::new(1, 0.0).filter(&Mistaken, children);
+        assert!(matches!(kept[0].game_state(), GameOver(_)));
+    }
+}
@@ -1,8 +1,9 @@
+use crate::error::{Error, Result};
 use crate::evaluator::Evaluator;
 use crate::probabilities::Probabilities;
 use bkgm::{utils::mcomb, Hypergammon, State};
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{self, BufReader, Read};
 use std::path::Path;
 
 use super::PartialEvaluator;
@@ -28,19 +29,28 @@ impl Evaluator<Hypergammon> for HyperEvaluator {
 }
 
 impl HyperEvaluator {
-    pub fn new() -> Option<Self> {
+    pub fn new() -> Result<Self> {
         Self::from_file("data/hyper.db")
     }
 
-    pub fn from_file(file_path: impl AsRef<Path>) -> Option<Self> {
-        let file = File::open(file_path).expect("File not found");
+    pub fn from_file(file_path: impl AsRef<Path>) -> Result<Self> {
+        let file_path = file_path.as_ref();
+        let file = File::open(file_path).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => Error::FileNotFound(file_path.to_path_buf()),
+            _ => Error::Io(err),
+        })?;
 
         let mut reader = BufReader::new(file);
 
         let mut buffer = [0u8; 20];
         let mut probs = Vec::new();
 
-        while reader.read_exact(&mut buffer).is_ok() {
+        loop {
+            match reader.read_exact(&mut buffer) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(Error::Io(err)),
+            }
             let wgbgb = [
                 f32::from_le_bytes(buffer[0..4].try_into().unwrap()),
                 f32::from_le_bytes(buffer[4..8].try_into().unwrap()),
@@ -60,9 +70,45 @@ impl HyperEvaluator {
         }
 
         if probs.len() == POSSIBLE {
-            Some(Self { probs })
+            Ok(Self { probs })
         } else {
-            None
+            Err(Error::TruncatedDatabase {
+                expected: POSSIBLE,
+                actual: probs.len(),
+            })
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::evaluator::HyperEvaluator;
+    use std::fs;
+
+    #[test]
+    fn missing_file() {
+        let result = HyperEvaluator::from_file("data/does-not-exist.db");
+        assert!(matches!(result, Err(Error::FileNotFound(_))));
+    }
+
+    #[test]
+    fn truncated_database() {
+        let path = std::env::temp_dir().join("staffa-truncated-hyper.db");
+        fs::write(&path, [0u8; 3 * 20 + 7]).unwrap();
+
+        let result = HyperEvaluator::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(Error::TruncatedDatabase { actual, .. }) => assert_eq!(actual, 3),
+            _ => panic!("Expected a truncated database"),
+        }
+    }
+
+    #[test]
+    fn read_error() {
+        // Opening a directory succeeds on Unix, but reading from it fails.
+        let result = HyperEvaluator::from_file(std::env::temp_dir());
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+}
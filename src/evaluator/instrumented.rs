@@ -0,0 +1,271 @@
+use crate::evaluator::{Evaluator, PartialEvaluator};
+use crate::probabilities::Probabilities;
+use bkgm::{Dice, State};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Latencies are counted in buckets of powers of two nanoseconds.
+const BUCKETS: usize = 64;
+
+/// Snapshot of the counters of an `InstrumentedEvaluator`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvaluatorStats {
+    pub name: String,
+    /// Calls of the evaluation methods, a batch counts once.
+    pub calls: u64,
+    /// Positions evaluated, summed over all calls.
+    pub positions: u64,
+    /// Calls of `best_position`.
+    pub moves: u64,
+    /// Legal moves considered by `best_position`, summed over all calls.
+    pub candidates: u64,
+    /// Time spent in the evaluation methods.
+    pub latency: Duration,
+    /// Number of calls taking less than 2^i but at least 2^(i-1) nanoseconds.
+    pub histogram: [u64; BUCKETS],
+}
+
+impl EvaluatorStats {
+    /// Average number of legal moves per `best_position` call.
+    pub fn branching_factor(&self) -> f32 {
+        match self.moves {
+            0 => 0.0,
+            moves => self.candidates as f32 / moves as f32,
+        }
+    }
+
+    pub fn mean_latency(&self) -> Duration {
+        match self.calls {
+            0 => Duration::ZERO,
+            calls => Duration::from_nanos((self.latency.as_nanos() / calls as u128) as u64),
+        }
+    }
+
+    pub fn positions_per_second(&self) -> f64 {
+        match self.latency.as_secs_f64() {
+            secs if secs > 0.0 => self.positions as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    /// Latency which `percentile` percent of the calls don't exceed, rounded up to a power of
+    /// two nanoseconds.
+    pub fn latency_percentile(&self, percentile: f64) -> Duration {
+        let target = (self.calls as f64 * percentile / 100.0).ceil() as u64;
+        let mut seen = 0;
+        for (i, count) in self.histogram.iter().enumerate() {
+            seen += count;
+            if seen >= target.max(1) {
+                return Duration::from_nanos(1u64.checked_shl(i as u32).unwrap_or(u64::MAX));
+            }
+        }
+        Duration::ZERO
+    }
+}
+
+impl fmt::Display for EvaluatorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} positions in {} calls ({:.0} positions/s)",
+            self.name,
+            self.positions,
+            self.calls,
+            self.positions_per_second()
+        )?;
+        writeln!(
+            f,
+            "  Moves: {}, average branching factor {:.1}",
+            self.moves,
+            self.branching_factor()
+        )?;
+        write!(
+            f,
+            "  Latency: total {:.2?}, mean {:.2?}, p50 {:.2?}, p90 {:.2?}, p99 {:.2?}",
+            self.latency,
+            self.mean_latency(),
+            self.latency_percentile(50.0),
+            self.latency_percentile(90.0),
+            self.latency_percentile(99.0)
+        )
+    }
+}
+
+/// Counts the calls, evaluated positions and latencies of another evaluator, to find out where
+/// the time of a duel or rollout goes. The counters are atomic, so the evaluator can be shared
+/// by the threads of a rollout.
+///
+/// `best_position` is delegated, so the evaluator plays as without instrumentation. It counts as
+/// one call evaluating all legal moves, even if the evaluator searches more positions, e.g.
+/// `MctsEvaluator`. To count those, instrument the evaluator it searches with.
+pub struct InstrumentedEvaluator<E: PartialEvaluator<G>, G: State> {
+    evaluator: E,
+    calls: AtomicU64,
+    positions: AtomicU64,
+    moves: AtomicU64,
+    candidates: AtomicU64,
+    nanos: AtomicU64,
+    histogram: [AtomicU64; BUCKETS],
+    phantom: PhantomData<G>,
+}
+
+impl<E: PartialEvaluator<G>, G: State> PartialEvaluator<G> for InstrumentedEvaluator<E, G> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.timed(1, || self.evaluator.try_eval(pos))
+    }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        self.timed(positions.len(), || self.evaluator.try_eval_batch(positions))
+    }
+
+    fn best_position(&self, pos: &G, dice: &Dice) -> G {
+        let candidates = pos.possible_positions(dice).len();
+        self.moves.fetch_add(1, Ordering::Relaxed);
+        self.candidates
+            .fetch_add(candidates as u64, Ordering::Relaxed);
+        self.timed(candidates, || self.evaluator.best_position(pos, dice))
+    }
+
+    fn name(&self) -> String {
+        self.evaluator.name()
+    }
+}
+
+impl<E: Evaluator<G>, G: State> Evaluator<G> for InstrumentedEvaluator<E, G> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.timed(1, || self.evaluator.eval(pos))
+    }
+
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        self.timed(positions.len(), || self.evaluator.eval_batch(positions))
+    }
+}
+
+impl<E: PartialEvaluator<G>, G: State> InstrumentedEvaluator<E, G> {
+    pub fn new(evaluator: E) -> Self {
+        Self {
+            evaluator,
+            calls: AtomicU64::new(0),
+            positions: AtomicU64::new(0),
+            moves: AtomicU64::new(0),
+            candidates: AtomicU64::new(0),
+            nanos: AtomicU64::new(0),
+            histogram: std::array::from_fn(|_| AtomicU64::new(0)),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn stats(&self) -> EvaluatorStats {
+        EvaluatorStats {
+            name: self.evaluator.name(),
+            calls: self.calls.load(Ordering::Relaxed),
+            positions: self.positions.load(Ordering::Relaxed),
+            moves: self.moves.load(Ordering::Relaxed),
+            candidates: self.candidates.load(Ordering::Relaxed),
+            latency: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+            histogram: std::array::from_fn(|i| self.histogram[i].load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Resets all counters.
+    pub fn reset(&self) {
+        let counters = [
+            &self.calls,
+            &self.positions,
+            &self.moves,
+            &self.candidates,
+            &self.nanos,
+        ];
+        for counter in counters.into_iter().chain(&self.histogram) {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn timed<T>(&self, positions: usize, evaluate: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = evaluate();
+        let nanos = start.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.positions
+            .fetch_add(positions as u64, Ordering::Relaxed);
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+        let bucket = (u64::BITS - nanos.leading_zeros()) as usize;
+        self.histogram[bucket.min(BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{
+        DynPartialEvaluator, Evaluator, InstrumentedEvaluator, MctsEvaluator, OffEvaluator,
+        PartialEvaluator,
+    };
+    use crate::test_utils::race_position;
+    use bkgm::{Backgammon, Dice, State};
+    use std::time::Duration;
+
+    #[test]
+    fn counts_calls_and_positions() {
+        let instrumented = InstrumentedEvaluator::new(OffEvaluator);
+        let pos = race_position();
+        let dice = Dice::new(6, 5);
+
+        assert_eq!(instrumented.eval(&pos), OffEvaluator.eval(&pos));
+        instrumented.eval_batch(&[pos, pos, pos]);
+        let best = instrumented.best_position(&pos, &dice);
+        assert_eq!(best, OffEvaluator.best_position(&pos, &dice));
+
+        let candidates = pos.possible_positions(&dice).len() as u64;
+        let stats = instrumented.stats();
+        assert_eq!(stats.calls, 3);
+        assert_eq!(stats.positions, 1 + 3 + candidates);
+        assert_eq!(stats.moves, 1);
+        assert_eq!(stats.branching_factor(), candidates as f32);
+        assert_eq!(stats.histogram.iter().sum::<u64>(), 3);
+
+        instrumented.reset();
+        assert_eq!(instrumented.stats().calls, 0);
+        assert_eq!(instrumented.stats().histogram.iter().sum::<u64>(), 0);
+    }
+
+    #[test]
+    fn keeps_the_move_choice() {
+        let mcts = || MctsEvaluator::new(OffEvaluator).with_iterations(50);
+        let instrumented = InstrumentedEvaluator::new(mcts());
+        let pos = race_position();
+        let dice = Dice::new(2, 1);
+
+        let dynamic: &dyn DynPartialEvaluator<Backgammon> = &instrumented;
+        let best = dynamic.best_position(&pos, &dice);
+        assert_eq!(best, mcts().best_position(&pos, &dice));
+        assert_eq!(instrumented.stats().moves, 1);
+    }
+
+    #[test]
+    fn latency_percentiles() {
+        let instrumented = InstrumentedEvaluator::<_, Backgammon>::new(OffEvaluator);
+        let mut stats = instrumented.stats();
+        assert_eq!(stats.latency_percentile(50.0), Duration::ZERO);
+
+        // 9 calls of about 1 µs and one of about 1 ms
+        stats.calls = 10;
+        stats.histogram[10] = 9;
+        stats.histogram[20] = 1;
+        assert_eq!(
+            stats.latency_percentile(50.0),
+            Duration::from_nanos(1 << 10)
+        );
+        assert_eq!(
+            stats.latency_percentile(90.0),
+            Duration::from_nanos(1 << 10)
+        );
+        assert_eq!(
+            stats.latency_percentile(99.0),
+            Duration::from_nanos(1 << 20)
+        );
+    }
+}
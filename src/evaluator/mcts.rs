@@ -0,0 +1,441 @@
+use crate::evaluator::{Evaluator, PartialEvaluator};
+use crate::probabilities::Probabilities;
+use bkgm::dice::ALL_21;
+use bkgm::{
+    Dice,
+    GameState::{GameOver, Ongoing},
+    State,
+};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Temperature of the softmax turning static equities into priors.
+const PRIOR_TEMPERATURE: f32 = 0.1;
+
+/// Trees with more nodes are discarded before a search instead of being reused.
+const MAX_TREE_SIZE: usize = 1_000_000;
+
+/// Position in the search tree, before the player on roll rolls the dice.
+struct Node<G> {
+    pos: G,
+    terminal: bool,
+    visits: u32,
+    /// Sum of the backed up probabilities, from the view of the player on roll.
+    sum: [f32; 6],
+    /// Chance node: one entry per roll of `ALL_21`, empty until the node is first searched.
+    rolls: Vec<Roll>,
+}
+
+/// Decision node: the player on roll chooses among the moves of a roll.
+#[derive(Default)]
+struct Roll {
+    visits: u32,
+    /// Empty until the roll is first searched.
+    moves: Vec<Move>,
+}
+
+struct Move {
+    node: usize,
+    prior: f32,
+}
+
+impl<G> Node<G> {
+    /// Every node starts with one visit: its static evaluation.
+    fn new(pos: G, terminal: bool, probs: Probabilities) -> Self {
+        Self {
+            pos,
+            terminal,
+            visits: 1,
+            sum: probs.to_slice(),
+            rolls: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, probs: &Probabilities) {
+        self.visits += 1;
+        for (s, p) in self.sum.iter_mut().zip(probs.to_slice()) {
+            *s += p;
+        }
+    }
+
+    fn mean(&self) -> Probabilities {
+        Probabilities::from(&self.sum)
+    }
+}
+
+/// Search tree shared by all searches of an `MctsEvaluator`. Positions are looked up in an
+/// index, so transpositions share their statistics and later searches reuse earlier ones.
+struct Tree<G> {
+    nodes: Vec<Node<G>>,
+    index: HashMap<G, usize>,
+}
+
+impl<G: State + Hash + Eq> Tree<G> {
+    fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.index.clear();
+    }
+
+    /// Indices of the nodes of `positions`. Missing nodes are created, their positions are
+    /// evaluated in a single batch.
+    fn nodes_of<E: Evaluator<G>>(&mut self, evaluator: &E, positions: &[G]) -> Vec<usize> {
+        let mut missing = Vec::new();
+        for pos in positions {
+            if pos.game_state() == Ongoing
+                && !self.index.contains_key(pos)
+                && !missing.contains(pos)
+            {
+                missing.push(*pos);
+            }
+        }
+        let mut evaluated = evaluator.eval_batch(&missing).into_iter();
+        positions
+            .iter()
+            .map(|pos| {
+                if let Some(&i) = self.index.get(pos) {
+                    return i;
+                }
+                let node = match pos.game_state() {
+                    GameOver(result) => Node::new(*pos, true, Probabilities::from_result(&result)),
+                    Ongoing => Node::new(*pos, false, evaluated.next().unwrap()),
+                };
+                self.nodes.push(node);
+                self.index.insert(*pos, self.nodes.len() - 1);
+                self.nodes.len() - 1
+            })
+            .collect()
+    }
+
+    /// Moves to `children` with priors from a softmax over their static equities.
+    fn expand<E: Evaluator<G>>(&mut self, evaluator: &E, children: &[G]) -> Vec<Move> {
+        let nodes = self.nodes_of(evaluator, children);
+        // Children are seen from the opponent, the player choosing the move gets the negation.
+        let values: Vec<f32> = nodes
+            .iter()
+            .map(|&i| -self.nodes[i].mean().equity())
+            .collect();
+        let best = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let weights: Vec<f32> = values
+            .iter()
+            .map(|value| ((value - best) / PRIOR_TEMPERATURE).exp())
+            .collect();
+        let total: f32 = weights.iter().sum();
+        nodes
+            .into_iter()
+            .zip(weights)
+            .map(|(node, weight)| Move {
+                node,
+                prior: weight / total,
+            })
+            .collect()
+    }
+
+    /// Index of the move to search next, by UCT or, with priors, by PUCT.
+    fn select_move(&self, moves: &[Move], visits: u32, settings: &Settings) -> usize {
+        let parent = (visits + 1) as f32;
+        let scores = moves.iter().map(|m| {
+            let child = &self.nodes[m.node];
+            let q = -child.mean().equity();
+            let n = child.visits as f32;
+            let u = if settings.priors {
+                settings.exploration * m.prior * parent.sqrt() / (1.0 + n)
+            } else {
+                settings.exploration * (parent.ln() / n).sqrt()
+            };
+            q + u
+        });
+        scores
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap()
+            .0
+    }
+
+    /// Index of the roll to search next. Instead of sampling, the roll which is furthest behind
+    /// its share of the visits is chosen, which removes the variance of the dice.
+    fn select_roll(&self, node: usize) -> usize {
+        let rolls = &self.nodes[node].rolls;
+        let visits: u32 = rolls.iter().map(|roll| roll.visits).sum();
+        ALL_21
+            .iter()
+            .zip(rolls)
+            // Each roll occurs n out of 36 times
+            .map(|((_, n), roll)| n / 36.0 * (visits + 1) as f32 - roll.visits as f32)
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap()
+            .0
+    }
+
+    /// Descends from `root` to a terminal node or to a roll which hasn't been searched yet,
+    /// expands that roll and backs up the value of its selected move.
+    fn iterate<E: Evaluator<G>>(&mut self, evaluator: &E, root: usize, settings: &Settings) {
+        let mut path: Vec<(usize, usize)> = Vec::new();
+        let mut node = root;
+        // Seen from the node after the last entry of the path
+        let value = loop {
+            if self.nodes[node].terminal {
+                let probs = self.nodes[node].mean();
+                self.nodes[node].add(&probs);
+                break probs;
+            }
+            // Hits allow positions to repeat, the search must not run in circles.
+            if path.iter().any(|&(visited, _)| visited == node) {
+                break self.nodes[node].mean();
+            }
+            if self.nodes[node].rolls.is_empty() {
+                self.nodes[node].rolls = ALL_21.iter().map(|_| Roll::default()).collect();
+            }
+            let roll = self.select_roll(node);
+            path.push((node, roll));
+
+            if self.nodes[node].rolls[roll].moves.is_empty() {
+                let dice: Dice = ALL_21[roll].0;
+                let children = self.nodes[node].pos.possible_positions(&dice);
+                let moves = self.expand(evaluator, &children);
+                let chosen = moves[self.select_move(&moves, 0, settings)].node;
+                self.nodes[node].rolls[roll].moves = moves;
+                break self.nodes[chosen].mean();
+            }
+            let roll = &self.nodes[node].rolls[roll];
+            node = roll.moves[self.select_move(&roll.moves, roll.visits, settings)].node;
+        };
+
+        let mut probs = value;
+        for (node, roll) in path.into_iter().rev() {
+            probs = probs.flip();
+            self.nodes[node].add(&probs);
+            self.nodes[node].rolls[roll].visits += 1;
+        }
+    }
+}
+
+/// Parameters of the tree policy.
+struct Settings {
+    exploration: f32,
+    priors: bool,
+}
+
+/// Monte Carlo tree search on top of another `Evaluator`.
+///
+/// Nodes are positions before the dice are rolled, each with a decision per roll of `ALL_21`.
+/// Rolls are visited in proportion to how often they occur. Moves are chosen by UCT or, with
+/// priors from the static evaluation of the moves, by PUCT. New positions are evaluated by the
+/// inner evaluator, the probabilities are averaged over all visits of a node.
+///
+/// The tree is kept between searches, so evaluating a position which has been reached in an
+/// earlier search continues where that search stopped.
+pub struct MctsEvaluator<E: Evaluator<G>, G: State + Hash + Eq> {
+    evaluator: E,
+    iterations: usize,
+    time_limit: Option<Duration>,
+    settings: Settings,
+    reuse: bool,
+    tree: Mutex<Tree<G>>,
+}
+
+impl<E: Evaluator<G>, G: State + Hash + Eq> PartialEvaluator<G> for MctsEvaluator<E, G> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        let probs = self.eval(pos);
+        probs.equity()
+    }
+
+    /// Searches the moves of `dice` and plays the most visited one.
+    fn best_position(&self, pos: &G, dice: &Dice) -> G {
+        let positions = pos.possible_positions(dice);
+        let mut tree = self.tree();
+        let moves = tree.expand(&self.evaluator, &positions);
+        let start = Instant::now();
+        for i in 0..self.iterations {
+            if self.out_of_time(start) {
+                break;
+            }
+            let chosen = tree.select_move(&moves, i as u32, &self.settings);
+            tree.iterate(&self.evaluator, moves[chosen].node, &self.settings);
+        }
+        // Ties, e.g. without any iterations, are broken by the equity.
+        let key = |m: &Move| {
+            let node = &tree.nodes[m.node];
+            (node.visits, -node.mean().equity())
+        };
+        let best = moves
+            .iter()
+            .max_by(|a, b| key(a).partial_cmp(&key(b)).unwrap())
+            .unwrap();
+        tree.nodes[best.node].pos
+    }
+
+    fn name(&self) -> String {
+        format!("MCTS {}", self.evaluator.name())
+    }
+}
+
+impl<E: Evaluator<G>, G: State + Hash + Eq> Evaluator<G> for MctsEvaluator<E, G> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        let mut tree = self.tree();
+        let root = tree.nodes_of(&self.evaluator, std::slice::from_ref(pos))[0];
+        let start = Instant::now();
+        for _ in 0..self.iterations {
+            if self.out_of_time(start) {
+                break;
+            }
+            tree.iterate(&self.evaluator, root, &self.settings);
+        }
+        tree.nodes[root].mean()
+    }
+}
+
+impl<E: Evaluator<G>, G: State + Hash + Eq> MctsEvaluator<E, G> {
+    /// UCT search with 1000 iterations and without time limit.
+    pub fn new(evaluator: E) -> Self {
+        Self {
+            evaluator,
+            iterations: 1000,
+            time_limit: None,
+            settings: Settings {
+                exploration: 1.0,
+                priors: false,
+            },
+            reuse: true,
+            tree: Mutex::new(Tree::new()),
+        }
+    }
+
+    /// Maximum number of iterations per search.
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Stops a search after `time_limit`, even if there are iterations left.
+    pub fn with_time_limit(mut self, time_limit: Duration) -> Self {
+        self.time_limit = Some(time_limit);
+        self
+    }
+
+    /// Weight of the exploration term, in units of equity.
+    pub fn with_exploration(mut self, exploration: f32) -> Self {
+        self.settings.exploration = exploration;
+        self
+    }
+
+    /// Uses PUCT with priors from the inner evaluator instead of UCT.
+    pub fn with_priors(mut self, priors: bool) -> Self {
+        self.settings.priors = priors;
+        self
+    }
+
+    /// Without reuse every search starts from an empty tree.
+    pub fn with_reuse(mut self, reuse: bool) -> Self {
+        self.reuse = reuse;
+        self
+    }
+
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Number of positions in the tree.
+    pub fn tree_size(&self) -> usize {
+        self.tree.lock().unwrap().nodes.len()
+    }
+
+    /// Discards the tree, e.g. at the start of a new game.
+    pub fn clear(&self) {
+        self.tree.lock().unwrap().clear();
+    }
+
+    /// The tree for the next search, emptied unless it may be reused.
+    fn tree(&self) -> MutexGuard<Tree<G>> {
+        let mut tree = self.tree.lock().unwrap();
+        if !self.reuse || tree.nodes.len() > MAX_TREE_SIZE {
+            tree.clear();
+        }
+        tree
+    }
+
+    fn out_of_time(&self, start: Instant) -> bool {
+        matches!(self.time_limit, Some(limit) if start.elapsed() >= limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{
+        Evaluator, EvaluatorSpec, MctsEvaluator, OffEvaluator, PartialEvaluator,
+    };
+    use crate::test_utils::race_position;
+    use bkgm::GameState::GameOver;
+    use bkgm::{bpos, Backgammon, Dice, State};
+    use std::time::Duration;
+
+    #[test]
+    fn every_roll_wins() {
+        let mcts = MctsEvaluator::new(OffEvaluator).with_iterations(200);
+        let pos = bpos!(x 1:1; o 24:1);
+
+        let probabilities = mcts.eval(&pos);
+        assert!(probabilities.win_normal > 0.99);
+        // All rolls lead to the same finished game
+        assert_eq!(mcts.tree_size(), 2);
+    }
+
+    #[test]
+    fn finds_winning_move() {
+        let mcts = MctsEvaluator::new(OffEvaluator)
+            .with_iterations(100)
+            .with_priors(true);
+        let pos = bpos!(x 6:1, 1:1; o 24:2);
+        let dice = Dice::new(6, 1);
+
+        let best = mcts.best_position(&pos, &dice);
+        assert!(matches!(best.game_state(), GameOver(_)));
+    }
+
+    #[test]
+    fn plays_through_spec() {
+        let spec: EvaluatorSpec = "mcts(100,random)".parse().unwrap();
+        let mcts = spec.build_partial::<Backgammon>().unwrap();
+        let pos = bpos!(x 6:1, 1:1; o 24:2);
+        let dice = Dice::new(6, 1);
+
+        // The boxed evaluator plays the most visited move, the winning one.
+        let best = mcts.best_position(&pos, &dice);
+        assert!(matches!(best.game_state(), GameOver(_)));
+    }
+
+    #[test]
+    fn tree_is_reused() {
+        let mcts = MctsEvaluator::new(OffEvaluator).with_iterations(50);
+        let pos = race_position();
+        mcts.eval(&pos);
+        let size = mcts.tree_size();
+        mcts.eval(&pos);
+        assert!(mcts.tree_size() > size);
+
+        mcts.clear();
+        assert_eq!(mcts.tree_size(), 0);
+    }
+
+    #[test]
+    fn time_limit_stops_search() {
+        let mcts = MctsEvaluator::new(OffEvaluator)
+            .with_iterations(usize::MAX)
+            .with_time_limit(Duration::ZERO);
+        let pos = race_position();
+
+        // Only the static evaluation of the root
+        let difference = mcts.eval(&pos).equity() - OffEvaluator.eval(&pos).equity();
+        assert!(difference.abs() < 1e-6);
+        assert_eq!(mcts.tree_size(), 1);
+    }
+}
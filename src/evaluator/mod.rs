@@ -1,19 +1,46 @@
 use bkgm::{Dice, State};
 use std::path::Path;
+use std::sync::Arc;
 
+mod budget;
+mod cache;
+mod cubeful;
+mod ensemble;
+mod filter;
 mod hyper;
-// mod mcts;
+mod instrumented;
+mod mcts;
+mod model;
+mod noisy;
 mod onnx;
+mod phase;
 mod ply;
 mod pubeval;
 mod rollout;
+mod server;
+mod spec;
+mod star;
 mod wildbg;
+use crate::error::Result;
 use crate::probabilities::Probabilities;
+pub(crate) use budget::Budget;
+pub use cache::{CacheStats, CachedEvaluator};
+pub use cubeful::{CubefulEvaluator, JanowskiEvaluator};
+pub use ensemble::EnsembleEvaluator;
+pub use filter::MoveFilter;
 pub use hyper::HyperEvaluator;
+pub use instrumented::{EvaluatorStats, InstrumentedEvaluator};
+pub use mcts::MctsEvaluator;
+pub use model::ModelShape;
+pub use noisy::{NoisyEvaluator, SkillLevel};
 pub use onnx::OnnxEvaluator;
+pub use phase::{PhaseEvaluator, PositionClass};
 pub use ply::PlyEvaluator;
 pub use pubeval::PubEval;
-pub use rollout::RolloutEvaluator;
+pub use rollout::{RolloutEvaluator, RolloutResult, RolloutSettings};
+pub use server::InferenceServer;
+pub use spec::{EvaluatorSpec, SpecGame};
+pub use star::StarEvaluator;
 pub use wildbg::WildbgEvaluator;
 
 pub trait PartialEvaluator<G: State>: Sized {
@@ -22,14 +49,43 @@ pub trait PartialEvaluator<G: State>: Sized {
     /// Examples of such strategies are a rollout or 1-ply inference of a neural net.
     fn try_eval(&self, pos: &G) -> f32;
 
+    /// Returns the evaluations of several positions at once.
+    /// Evaluators with a per-call overhead, like neural nets, should override this.
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        positions.iter().map(|pos| self.try_eval(pos)).collect()
+    }
+
     fn best_position(&self, pos: &G, dice: &Dice) -> G {
-        *pos.possible_positions(dice)
-            .iter()
-            .map(|pos| (pos, self.try_eval(&pos)))
+        let positions = pos.possible_positions(dice);
+        let values = self.try_eval_batch(&positions);
+        positions
+            .into_iter()
+            .zip(values)
             .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
             .unwrap()
             .0
     }
+
+    /// Short description of the evaluator, used to label analysis results.
+    fn name(&self) -> String {
+        short_type_name::<Self>()
+    }
+}
+
+/// `try_eval_batch` of evaluators which evaluate batches faster than single positions.
+pub(crate) fn equity_batch<G: State>(evaluator: &impl Evaluator<G>, positions: &[G]) -> Vec<f32> {
+    evaluator
+        .eval_batch(positions)
+        .iter()
+        .map(Probabilities::equity)
+        .collect()
+}
+
+/// Name of a type without module path and generic parameters, e.g. `PubEval`.
+fn short_type_name<T>() -> String {
+    let name = std::any::type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name).to_string()
 }
 
 pub trait Evaluator<G: State>: PartialEvaluator<G> + Sized {
@@ -37,18 +93,120 @@ pub trait Evaluator<G: State>: PartialEvaluator<G> + Sized {
     /// Implementing types will calculate the probabilities with different strategies.
     /// Examples of such strategies are a rollout or 1-ply inference of a neural net.
     fn eval(&self, pos: &G) -> Probabilities;
+
+    /// Returns cubeless evaluations of several positions at once.
+    /// Evaluators with a per-call overhead, like neural nets, should override this.
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        positions.iter().map(|pos| self.eval(pos)).collect()
+    }
+}
+
+/// Object-safe counterpart of `PartialEvaluator`, implemented for every `PartialEvaluator`.
+/// Allows choosing evaluators at runtime, see `EvaluatorSpec`.
+pub trait DynPartialEvaluator<G: State>: Send + Sync {
+    fn dyn_try_eval(&self, pos: &G) -> f32;
+    fn dyn_try_eval_batch(&self, positions: &[G]) -> Vec<f32>;
+    fn dyn_best_position(&self, pos: &G, dice: &Dice) -> G;
+    fn dyn_name(&self) -> String;
+}
+
+impl<G: State, E: PartialEvaluator<G> + Send + Sync> DynPartialEvaluator<G> for E {
+    fn dyn_try_eval(&self, pos: &G) -> f32 {
+        self.try_eval(pos)
+    }
+
+    fn dyn_try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        self.try_eval_batch(positions)
+    }
+
+    fn dyn_best_position(&self, pos: &G, dice: &Dice) -> G {
+        self.best_position(pos, dice)
+    }
+
+    fn dyn_name(&self) -> String {
+        self.name()
+    }
 }
 
+/// Object-safe counterpart of `Evaluator`, implemented for every `Evaluator`.
+pub trait DynEvaluator<G: State>: DynPartialEvaluator<G> {
+    fn dyn_eval(&self, pos: &G) -> Probabilities;
+    fn dyn_eval_batch(&self, positions: &[G]) -> Vec<Probabilities>;
+}
+
+impl<G: State, E: Evaluator<G> + Send + Sync> DynEvaluator<G> for E {
+    fn dyn_eval(&self, pos: &G) -> Probabilities {
+        self.eval(pos)
+    }
+
+    fn dyn_eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        self.eval_batch(positions)
+    }
+}
+
+/// Implements `PartialEvaluator` for a pointer to one of the object-safe traits.
+macro_rules! impl_partial_evaluator_for_pointer {
+    ($pointer:ty) => {
+        impl<G: State> PartialEvaluator<G> for $pointer {
+            fn try_eval(&self, pos: &G) -> f32 {
+                (**self).dyn_try_eval(pos)
+            }
+
+            fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+                (**self).dyn_try_eval_batch(positions)
+            }
+
+            fn best_position(&self, pos: &G, dice: &Dice) -> G {
+                (**self).dyn_best_position(pos, dice)
+            }
+
+            fn name(&self) -> String {
+                (**self).dyn_name()
+            }
+        }
+    };
+}
+
+/// Implements `PartialEvaluator` and `Evaluator` for a pointer to one of the object-safe traits.
+macro_rules! impl_evaluator_for_pointer {
+    ($pointer:ty) => {
+        impl_partial_evaluator_for_pointer!($pointer);
+
+        impl<G: State> Evaluator<G> for $pointer {
+            fn eval(&self, pos: &G) -> Probabilities {
+                (**self).dyn_eval(pos)
+            }
+
+            fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+                (**self).dyn_eval_batch(positions)
+            }
+        }
+    };
+}
+
+impl_partial_evaluator_for_pointer!(Box<dyn DynPartialEvaluator<G>>);
+impl_partial_evaluator_for_pointer!(Arc<dyn DynPartialEvaluator<G>>);
+impl_partial_evaluator_for_pointer!(&dyn DynPartialEvaluator<G>);
+impl_evaluator_for_pointer!(Box<dyn DynEvaluator<G>>);
+impl_evaluator_for_pointer!(Arc<dyn DynEvaluator<G>>);
+impl_evaluator_for_pointer!(&dyn DynEvaluator<G>);
+impl_evaluator_for_pointer!(Box<dyn DynNNEvaluator<G>>);
+impl_evaluator_for_pointer!(Arc<dyn DynNNEvaluator<G>>);
+impl_evaluator_for_pointer!(&dyn DynNNEvaluator<G>);
+
 pub trait NNEvaluator<G: State>: Evaluator<G> + Sized {
     const MODEL_PATH: &'static str;
     const NUM_INTPUTS: usize;
     const NUM_OUTPUTS: usize;
 
-    fn with_default_model() -> Option<Self> {
+    fn with_default_model() -> Result<Self> {
         Self::from_file_path(Self::MODEL_PATH)
     }
 
-    fn from_file_path(file_path: impl AsRef<Path>) -> Option<Self>;
+    fn from_file_path(file_path: impl AsRef<Path>) -> Result<Self>;
+
+    /// Input and output shape detected when loading the model.
+    fn shape(&self) -> &ModelShape;
 
     fn input_labels(&self) -> Vec<String>;
     fn output_labels(&self) -> Vec<String>;
@@ -57,6 +215,38 @@ pub trait NNEvaluator<G: State>: Evaluator<G> + Sized {
     fn output_vec(&self, position: &G) -> Vec<f32>;
 }
 
+/// Object-safe counterpart of `NNEvaluator`, implemented for every `NNEvaluator`.
+/// Constructors and constants are left out, they need a concrete type.
+pub trait DynNNEvaluator<G: State>: DynEvaluator<G> {
+    fn dyn_shape(&self) -> &ModelShape;
+    fn dyn_input_labels(&self) -> Vec<String>;
+    fn dyn_output_labels(&self) -> Vec<String>;
+    fn dyn_input_vec(&self, position: &G) -> Vec<f32>;
+    fn dyn_output_vec(&self, position: &G) -> Vec<f32>;
+}
+
+impl<G: State, E: NNEvaluator<G> + Send + Sync> DynNNEvaluator<G> for E {
+    fn dyn_shape(&self) -> &ModelShape {
+        self.shape()
+    }
+
+    fn dyn_input_labels(&self) -> Vec<String> {
+        self.input_labels()
+    }
+
+    fn dyn_output_labels(&self) -> Vec<String> {
+        self.output_labels()
+    }
+
+    fn dyn_input_vec(&self, position: &G) -> Vec<f32> {
+        self.input_vec(position)
+    }
+
+    fn dyn_output_vec(&self, position: &G) -> Vec<f32> {
+        self.output_vec(position)
+    }
+}
+
 pub struct RandomEvaluator;
 
 impl<G: State> PartialEvaluator<G> for RandomEvaluator {
@@ -96,3 +286,86 @@ impl RandomEvaluator {
         RandomEvaluator {}
     }
 }
+
+#[cfg(test)]
+/// Use this for unit tests where results must be traced back to positions.
+/// The winning chance is the share of borne off checkers of the player on roll.
+pub(crate) struct OffEvaluator;
+
+#[cfg(test)]
+impl<G: State> PartialEvaluator<G> for OffEvaluator {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.eval(pos).equity()
+    }
+}
+
+#[cfg(test)]
+impl<G: State> Evaluator<G> for OffEvaluator {
+    fn eval(&self, pos: &G) -> Probabilities {
+        let win = pos.x_off() as f32 / 15.0;
+        Probabilities {
+            win_normal: win,
+            lose_normal: 1.0 - win,
+            ..Probabilities::empty()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dice::FastrandDice;
+    use crate::duel::Duel;
+    use crate::evaluator::{
+        DynEvaluator, DynPartialEvaluator, Evaluator, OffEvaluator, PartialEvaluator, PlyEvaluator,
+        PubEval, RandomEvaluator,
+    };
+    use bkgm::{bpos, Backgammon, Dice, State};
+    use std::sync::Arc;
+
+    /// Ranks all positions the same, but has its own move choice: the last legal move.
+    struct LastMove;
+
+    impl PartialEvaluator<Backgammon> for LastMove {
+        fn try_eval(&self, _pos: &Backgammon) -> f32 {
+            0.0
+        }
+
+        fn best_position(&self, pos: &Backgammon, dice: &Dice) -> Backgammon {
+            *pos.possible_positions(dice).last().unwrap()
+        }
+    }
+
+    #[test]
+    fn evaluators_chosen_at_runtime() {
+        let evaluators: Vec<Box<dyn DynEvaluator<Backgammon>>> =
+            vec![Box::new(RandomEvaluator::new()), Box::new(OffEvaluator)];
+        let pos = bpos!(x 1:1; o 24:1);
+
+        let ply = PlyEvaluator::new(&*evaluators[1], 1);
+        assert_eq!(ply.eval(&pos).win_normal, 1.0);
+        assert!(evaluators[1].eval(&pos).win_normal < 1.0);
+    }
+
+    #[test]
+    fn duel_between_boxed_evaluators() {
+        let pubeval: Arc<dyn DynPartialEvaluator<Backgammon>> = Arc::new(PubEval::new());
+        let random: Box<dyn DynPartialEvaluator<Backgammon>> = Box::new(RandomEvaluator::new());
+        let duel = Duel::new(pubeval.clone(), random);
+        assert_eq!(duel.duel(&mut FastrandDice::with_seed(1)).sum(), 2);
+    }
+
+    #[test]
+    fn pointers_keep_the_move_choice() {
+        let pos = Backgammon::new();
+        let dice = Dice::new(3, 1);
+        let expected = LastMove.best_position(&pos, &dice);
+        assert_ne!(expected, pos.possible_positions(&dice)[0]);
+
+        let boxed: Box<dyn DynPartialEvaluator<Backgammon>> = Box::new(LastMove);
+        assert_eq!(boxed.best_position(&pos, &dice), expected);
+        let shared: Arc<dyn DynPartialEvaluator<Backgammon>> = Arc::new(LastMove);
+        assert_eq!(shared.best_position(&pos, &dice), expected);
+        let borrowed: &dyn DynPartialEvaluator<Backgammon> = &LastMove;
+        assert_eq!(borrowed.best_position(&pos, &dice), expected);
+    }
+}
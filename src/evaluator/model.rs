@@ -0,0 +1,187 @@
+use crate::error::{Error, Result};
+use crate::probabilities::Probabilities;
+use std::path::Path;
+use tract_onnx::prelude::*;
+use tract_onnx::tract_hir::infer::Factoid;
+
+#[allow(clippy::type_complexity)]
+pub(crate) type Model =
+    RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
+
+/// Shape of the input and output of a loaded model.
+/// Symbolic dimensions, like the batch size, are `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModelShape {
+    pub input: Vec<Option<usize>>,
+    pub output: Vec<Option<usize>>,
+}
+
+impl ModelShape {
+    /// Number of inputs per position, `None` if the last input dimension is symbolic.
+    pub fn num_inputs(&self) -> Option<usize> {
+        self.input.last().copied().flatten()
+    }
+
+    /// Number of outputs per position, `None` if the last output dimension is symbolic.
+    pub fn num_outputs(&self) -> Option<usize> {
+        self.output.last().copied().flatten()
+    }
+}
+
+/// Loads an ONNX model taking `num_inputs` values per position and returning `num_outputs`.
+///
+/// The input shape is fixed to `[N, num_inputs]` with a symbolic batch size `N`, so the same
+/// model can evaluate any number of positions. Models declaring a different number of inputs or
+/// producing a different number of outputs are rejected.
+pub(crate) fn load(
+    file_path: impl AsRef<Path>,
+    num_inputs: usize,
+    num_outputs: usize,
+) -> Result<(Model, ModelShape)> {
+    let file_path = file_path.as_ref();
+    if !file_path.is_file() {
+        return Err(Error::FileNotFound(file_path.to_path_buf()));
+    }
+    let mut model = onnx().model_for_path(file_path).map_err(malformed)?;
+
+    // Only the last dimension is checked, the batch size is usually symbolic.
+    let input = model.input_fact(0).map_err(malformed)?;
+    let declared = if input.shape.is_open() {
+        None
+    } else {
+        input.shape.dims().last().and_then(|dim| dim.concretize())
+    };
+    if let Some(actual) = declared.and_then(|dim| dim.as_i64()) {
+        if actual != num_inputs as i64 {
+            return Err(Error::InputShape {
+                expected: num_inputs,
+                actual: actual as usize,
+            });
+        }
+    }
+
+    let batch = model.symbol_table.sym("N");
+    model
+        .set_input_fact(0, f32::fact([batch.to_dim(), num_inputs.to_dim()]).into())
+        .map_err(malformed)?;
+    let model = model.into_typed().map_err(malformed)?;
+
+    let shape = ModelShape {
+        input: dims(&model.input_fact(0).map_err(malformed)?.shape),
+        output: dims(&model.output_fact(0).map_err(malformed)?.shape),
+    };
+    match shape.num_outputs() {
+        Some(actual) if actual != num_outputs => {
+            return Err(Error::OutputShape {
+                expected: num_outputs,
+                actual,
+            })
+        }
+        Some(_) => {}
+        None => {
+            return Err(Error::MalformedModel(
+                "the number of outputs is symbolic".to_string(),
+            ))
+        }
+    }
+
+    let model = model
+        .into_optimized()
+        .and_then(|model| model.into_runnable())
+        .map_err(malformed)?;
+    Ok((model, shape))
+}
+
+fn dims(shape: &ShapeFact) -> Vec<Option<usize>> {
+    shape
+        .to_tvec()
+        .iter()
+        .map(|dim| dim.as_i64().map(|dim| dim as usize))
+        .collect()
+}
+
+fn malformed(err: TractError) -> Error {
+    Error::MalformedModel(format!("{:#}", err))
+}
+
+/// Runs `model` once on all positions in `inputs`, which holds `num_inputs` values per position.
+/// Returns the output vector of each position.
+pub(crate) fn run_batch(model: &Model, inputs: Vec<f32>, num_inputs: usize) -> Vec<Vec<f32>> {
+    let batch_size = inputs.len() / num_inputs;
+    if batch_size == 0 {
+        return vec![];
+    }
+    let tensor = tract_ndarray::Array2::from_shape_vec((batch_size, num_inputs), inputs)
+        .unwrap()
+        .into_tensor();
+
+    // run the model on the input
+    let result = model.run(tvec!(tensor.into())).unwrap();
+    let array_view = result[0].to_array_view::<f32>().unwrap();
+    let outputs: Vec<f32> = array_view.iter().copied().collect();
+    outputs
+        .chunks(outputs.len() / batch_size)
+        .map(|chunk| chunk.to_vec())
+        .collect()
+}
+
+/// Interprets the six outputs of a model in the order of the fields of `Probabilities`.
+pub(crate) fn probabilities(output: &[f32]) -> Probabilities {
+    Probabilities {
+        win_normal: output[0],
+        win_gammon: output[1],
+        win_bg: output[2],
+        lose_normal: output[3],
+        lose_gammon: output[4],
+        lose_bg: output[5],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load, ModelShape};
+    use crate::error::Error;
+    use crate::inputs::NUM_INPUTS;
+
+    /// A single MatMul from `NUM_INPUTS` inputs to 6 outputs with a symbolic batch size `N`.
+    const MODEL: &str = "tests/data/linear.onnx";
+
+    #[test]
+    fn symbolic_dimensions_are_none() {
+        let shape = ModelShape {
+            input: vec![None, Some(NUM_INPUTS)],
+            output: vec![None, None],
+        };
+        assert_eq!(shape.num_inputs(), Some(NUM_INPUTS));
+        assert_eq!(shape.num_outputs(), None);
+    }
+
+    #[test]
+    fn loads_matching_shape() {
+        let (_, shape) = load(MODEL, NUM_INPUTS, 6).unwrap();
+        assert_eq!(shape.num_inputs(), Some(NUM_INPUTS));
+        assert_eq!(shape.num_outputs(), Some(6));
+    }
+
+    #[test]
+    fn rejects_other_number_of_inputs() {
+        let result = load(MODEL, NUM_INPUTS + 1, 6);
+        assert!(matches!(
+            result,
+            Err(Error::InputShape { expected, actual })
+                if expected == NUM_INPUTS + 1 && actual == NUM_INPUTS
+        ));
+    }
+
+    #[test]
+    fn rejects_other_number_of_outputs() {
+        let result = load(MODEL, NUM_INPUTS, 5);
+        assert!(matches!(
+            result,
+            Err(Error::OutputShape {
+                expected: 5,
+                actual: 6
+            })
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::dice::mix_seed;
+use crate::error::{Error, Result};
+use crate::evaluator::PartialEvaluator;
+use bkgm::State;
+use std::f64::consts::PI;
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+/// Named strengths of a `NoisyEvaluator`, following gnubg's player settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkillLevel {
+    Beginner,
+    Casual,
+    Intermediate,
+    Advanced,
+    Expert,
+}
+
+impl SkillLevel {
+    /// Standard deviation of the noise added to equities.
+    pub fn noise(&self) -> f32 {
+        match self {
+            SkillLevel::Beginner => 0.060,
+            SkillLevel::Casual => 0.050,
+            SkillLevel::Intermediate => 0.040,
+            SkillLevel::Advanced => 0.015,
+            SkillLevel::Expert => 0.0,
+        }
+    }
+}
+
+impl FromStr for SkillLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "beginner" => Ok(SkillLevel::Beginner),
+            "casual" => Ok(SkillLevel::Casual),
+            "intermediate" => Ok(SkillLevel::Intermediate),
+            "advanced" => Ok(SkillLevel::Advanced),
+            "expert" => Ok(SkillLevel::Expert),
+            _ => Err(Error::InvalidSpec(format!("unknown skill level '{}'", s))),
+        }
+    }
+}
+
+impl fmt::Display for SkillLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SkillLevel::Beginner => "beginner",
+            SkillLevel::Casual => "casual",
+            SkillLevel::Intermediate => "intermediate",
+            SkillLevel::Advanced => "advanced",
+            SkillLevel::Expert => "expert",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Weaker player: adds normally distributed noise to the equities of another evaluator, so
+/// that `best_position` sometimes picks worse moves.
+///
+/// The noise only depends on the seed and the position, so games are reproducible and a
+/// position is always judged the same way.
+pub struct NoisyEvaluator<E: PartialEvaluator<G>, G: State + Hash> {
+    evaluator: E,
+    noise: f32,
+    seed: u64,
+    phantom: PhantomData<G>,
+}
+
+impl<E: PartialEvaluator<G>, G: State + Hash> PartialEvaluator<G> for NoisyEvaluator<E, G> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.evaluator.try_eval(pos) + self.noise_of(pos)
+    }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        self.evaluator
+            .try_eval_batch(positions)
+            .into_iter()
+            .zip(positions)
+            .map(|(value, pos)| value + self.noise_of(pos))
+            .collect()
+    }
+
+    fn name(&self) -> String {
+        format!("{} with noise {:.3}", self.evaluator.name(), self.noise)
+    }
+}
+
+impl<E: PartialEvaluator<G>, G: State + Hash> NoisyEvaluator<E, G> {
+    /// `noise` is the standard deviation added to equities. The seed is random.
+    pub fn new(evaluator: E, noise: f32) -> Self {
+        Self {
+            evaluator,
+            noise,
+            seed: fastrand::u64(..),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn from_skill(evaluator: E, skill: SkillLevel) -> Self {
+        Self::new(evaluator, skill.noise())
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn noise(&self) -> f32 {
+        self.noise
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn noise_of(&self, pos: &G) -> f32 {
+        if self.noise == 0.0 {
+            return 0.0;
+        }
+        let mut rng = fastrand::Rng::with_seed(mix_seed(self.seed, pos));
+        // Box-Muller transform, the first uniform number must not be 0.
+        let u1 = 1.0 - rng.f64();
+        let u2 = rng.f64();
+        let gaussian = (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
+        self.noise * gaussian as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{NoisyEvaluator, PartialEvaluator, PubEval, SkillLevel};
+    use bkgm::dice::ALL_21;
+    use bkgm::{Backgammon, State};
+
+    fn positions() -> Vec<Backgammon> {
+        let pos = Backgammon::new();
+        ALL_21
+            .iter()
+            .flat_map(|(dice, _)| pos.possible_positions(dice))
+            .collect()
+    }
+
+    #[test]
+    fn noise_is_deterministic_by_seed() {
+        let positions = positions();
+        let first = NoisyEvaluator::new(PubEval::new(), 0.05).with_seed(7);
+        let second = NoisyEvaluator::new(PubEval::new(), 0.05).with_seed(7);
+        let other = NoisyEvaluator::new(PubEval::new(), 0.05).with_seed(8);
+
+        let values = first.try_eval_batch(&positions);
+        assert_eq!(values, second.try_eval_batch(&positions));
+        assert_ne!(values, other.try_eval_batch(&positions));
+        assert_eq!(values[3], first.try_eval(&positions[3]));
+    }
+
+    #[test]
+    fn noise_has_given_deviation() {
+        let positions = positions();
+        let pubeval = PubEval::new();
+        let noisy = NoisyEvaluator::new(PubEval::new(), 0.5).with_seed(1);
+
+        let differences: Vec<f32> = pubeval
+            .try_eval_batch(&positions)
+            .iter()
+            .zip(noisy.try_eval_batch(&positions))
+            .map(|(plain, noisy)| noisy - plain)
+            .collect();
+        let n = differences.len() as f32;
+        let mean = differences.iter().sum::<f32>() / n;
+        let deviation = (differences.iter().map(|d| d * d).sum::<f32>() / n).sqrt();
+        assert!(mean.abs() < 0.1, "mean {}", mean);
+        assert!((deviation - 0.5).abs() < 0.1, "deviation {}", deviation);
+    }
+
+    #[test]
+    fn expert_plays_without_noise() {
+        let positions = positions();
+        let expert = NoisyEvaluator::from_skill(PubEval::new(), SkillLevel::Expert);
+        assert_eq!(
+            expert.try_eval_batch(&positions),
+            PubEval::new().try_eval_batch(&positions)
+        );
+        assert_eq!("Casual".parse::<SkillLevel>().unwrap(), SkillLevel::Casual);
+    }
+}
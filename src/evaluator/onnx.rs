@@ -1,16 +1,19 @@
-use crate::evaluator::{Evaluator, PartialEvaluator};
-use crate::inputs::Inputs;
+use super::model::{self, Model, ModelShape};
+use crate::error::Result;
+use crate::evaluator::{equity_batch, Evaluator, PartialEvaluator};
+use crate::inputs::{Inputs, NUM_INPUTS};
 use crate::probabilities::Probabilities;
 use std::marker::PhantomData;
 use std::path::Path;
-use tract_onnx::prelude::*;
 
 use super::State;
 
+const NUM_OUTPUTS: usize = 6;
+
 #[derive(Clone)]
 pub struct OnnxEvaluator<G: State> {
-    #[allow(clippy::type_complexity)]
-    model: RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>,
+    model: Model,
+    shape: ModelShape,
     phantom: PhantomData<G>,
 }
 
@@ -19,35 +22,45 @@ impl<G: State> PartialEvaluator<G> for OnnxEvaluator<G> {
         let probs = self.eval(pos);
         probs.equity()
     }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        equity_batch(self, positions)
+    }
 }
 
 impl<G: State> Evaluator<G> for OnnxEvaluator<G> {
     fn eval(&self, pos: &G) -> Probabilities {
         let output = self.output_vec(pos);
-        Probabilities {
-            win_normal: output[0],
-            win_gammon: output[1],
-            win_bg: output[2],
-            lose_normal: output[3],
-            lose_gammon: output[4],
-            lose_bg: output[5],
-        }
+        model::probabilities(&output)
+    }
+
+    /// Evaluates all positions with a single run of the model.
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        let inputs = positions.iter().flat_map(|pos| self.inputs(pos)).collect();
+        model::run_batch(&self.model, inputs, NUM_INPUTS)
+            .iter()
+            .map(|output| model::probabilities(output))
+            .collect()
     }
 }
 
 impl<G: State> OnnxEvaluator<G> {
-    pub fn with_default_model() -> Option<Self> {
+    pub fn with_default_model() -> Result<Self> {
         Self::from_file_path("model/staffa.onnx")
     }
 
-    pub fn from_file_path(file_path: impl AsRef<Path>) -> Option<Self> {
-        match Self::model(file_path) {
-            Ok(model) => Some(Self {
-                model,
-                phantom: PhantomData,
-            }),
-            Err(_) => None,
-        }
+    pub fn from_file_path(file_path: impl AsRef<Path>) -> Result<Self> {
+        let (model, shape) = model::load(file_path, NUM_INPUTS, NUM_OUTPUTS)?;
+        Ok(Self {
+            model,
+            shape,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Input and output shape detected when loading the model.
+    pub fn shape(&self) -> &ModelShape {
+        &self.shape
     }
 
     pub fn inputs(&self, position: &G) -> Vec<f32> {
@@ -57,15 +70,9 @@ impl<G: State> OnnxEvaluator<G> {
 
     pub fn output_vec(&self, position: &G) -> Vec<f32> {
         let inputs = self.inputs(position);
-        let tract_inputs = tract_ndarray::Array1::from_vec(inputs)
-            .into_shape([1, crate::inputs::NUM_INPUTS])
-            .unwrap();
-        let tensor = tract_inputs.into_tensor();
-
-        // run the model on the input
-        let result = self.model.run(tvec!(tensor.into())).unwrap();
-        let array_view = result[0].to_array_view::<f32>().unwrap();
-        array_view.iter().map(|&x| x).collect()
+        model::run_batch(&self.model, inputs, NUM_INPUTS)
+            .pop()
+            .unwrap()
     }
 
     pub fn input_labels(&self) -> Vec<String> {
@@ -102,18 +109,6 @@ impl<G: State> OnnxEvaluator<G> {
         ];
         labels.iter().map(|s| s.to_string()).collect()
     }
-
-    #[allow(clippy::type_complexity)]
-    fn model(
-        file_path: impl AsRef<Path>,
-    ) -> TractResult<RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>>
-    {
-        let model = onnx()
-            .model_for_path(file_path)?
-            .into_optimized()?
-            .into_runnable()?;
-        Ok(model)
-    }
 }
 
 /// The following tests mainly test the quality of the neural nets
@@ -121,8 +116,15 @@ impl<G: State> OnnxEvaluator<G> {
 mod tests {
     use super::super::Evaluator;
     use super::OnnxEvaluator;
+    use crate::error::Error;
     use bkgm::{bpos, Backgammon};
 
+    #[test]
+    fn missing_model() {
+        let result = OnnxEvaluator::<Backgammon>::from_file_path("model/does-not-exist.onnx");
+        assert!(matches!(result, Err(Error::FileNotFound(_))));
+    }
+
     #[test]
     fn eval_certain_win_normal() {
         let onnx = OnnxEvaluator::with_default_model().unwrap();
@@ -0,0 +1,165 @@
+use crate::evaluator::{equity_batch, Evaluator, PartialEvaluator};
+use crate::probabilities::Probabilities;
+use bkgm::{
+    position::GamePhase,
+    position::Phase::{Contact, Race},
+    GameState, State,
+};
+use std::marker::PhantomData;
+
+/// Class of a position, deciding which evaluator of a `PhaseEvaluator` is responsible.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionClass {
+    /// The game is over, the result is known exactly.
+    Over,
+    /// No contact and all checkers of both players are in their home boards.
+    Bearoff,
+    /// No contact, but not yet a bear-off for both players.
+    Race,
+    Contact,
+}
+
+impl PositionClass {
+    pub fn of<G: State>(pos: &G) -> Self {
+        match pos.phase() {
+            GamePhase::GameOver(_) => PositionClass::Over,
+            GamePhase::Ongoing(Contact) => PositionClass::Contact,
+            GamePhase::Ongoing(Race) => {
+                // In a race no checkers are on the bar, it's enough to look at the outer points.
+                let x_home = (7..=24).all(|i| pos.pip(i) <= 0);
+                let o_home = (1..=18).all(|i| pos.pip(i) >= 0);
+                if x_home && o_home {
+                    PositionClass::Bearoff
+                } else {
+                    PositionClass::Race
+                }
+            }
+        }
+    }
+}
+
+/// Routes each position to a different evaluator depending on its `PositionClass`, the way
+/// gnubg combines its contact net, race net and bear-off database.
+/// Positions where the game is over are not evaluated but get their exact result.
+pub struct PhaseEvaluator<C: Evaluator<G>, R: Evaluator<G>, B: Evaluator<G>, G: State> {
+    contact: C,
+    race: R,
+    bearoff: B,
+    phantom: PhantomData<G>,
+}
+
+impl<C: Evaluator<G>, R: Evaluator<G>, B: Evaluator<G>, G: State> PartialEvaluator<G>
+    for PhaseEvaluator<C, R, B, G>
+{
+    fn try_eval(&self, pos: &G) -> f32 {
+        let probs = self.eval(pos);
+        probs.equity()
+    }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        equity_batch(self, positions)
+    }
+}
+
+impl<C: Evaluator<G>, R: Evaluator<G>, B: Evaluator<G>, G: State> Evaluator<G>
+    for PhaseEvaluator<C, R, B, G>
+{
+    fn eval(&self, pos: &G) -> Probabilities {
+        match PositionClass::of(pos) {
+            PositionClass::Over => match pos.game_state() {
+                GameState::GameOver(result) => Probabilities::from_result(&result),
+                GameState::Ongoing => unreachable!(),
+            },
+            PositionClass::Bearoff => self.bearoff.eval(pos),
+            PositionClass::Race => self.race.eval(pos),
+            PositionClass::Contact => self.contact.eval(pos),
+        }
+    }
+
+    /// Each evaluator gets a single batch with the positions it is responsible for.
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        let classes: Vec<PositionClass> = positions.iter().map(PositionClass::of).collect();
+        let of_class = |class: PositionClass| -> Vec<G> {
+            positions
+                .iter()
+                .zip(&classes)
+                .filter(|(_, c)| **c == class)
+                .map(|(pos, _)| *pos)
+                .collect()
+        };
+        let mut bearoff = self
+            .bearoff
+            .eval_batch(&of_class(PositionClass::Bearoff))
+            .into_iter();
+        let mut race = self
+            .race
+            .eval_batch(&of_class(PositionClass::Race))
+            .into_iter();
+        let mut contact = self
+            .contact
+            .eval_batch(&of_class(PositionClass::Contact))
+            .into_iter();
+
+        positions
+            .iter()
+            .zip(classes)
+            .map(|(pos, class)| match class {
+                PositionClass::Over => self.eval(pos),
+                PositionClass::Bearoff => bearoff.next().unwrap(),
+                PositionClass::Race => race.next().unwrap(),
+                PositionClass::Contact => contact.next().unwrap(),
+            })
+            .collect()
+    }
+}
+
+impl<C: Evaluator<G>, R: Evaluator<G>, B: Evaluator<G>, G: State> PhaseEvaluator<C, R, B, G> {
+    pub fn new(contact: C, race: R, bearoff: B) -> Self {
+        Self {
+            contact,
+            race,
+            bearoff,
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{
+        Evaluator, OffEvaluator, PhaseEvaluator, PlyEvaluator, PositionClass, RandomEvaluator,
+    };
+    use bkgm::{bpos, Backgammon, State};
+
+    #[test]
+    fn classify_positions() {
+        assert_eq!(
+            PositionClass::of(&Backgammon::new()),
+            PositionClass::Contact
+        );
+        assert_eq!(
+            PositionClass::of(&bpos!(x 12:1; o 13:1)),
+            PositionClass::Race
+        );
+        assert_eq!(
+            PositionClass::of(&bpos!(x 6:2; o 19:2)),
+            PositionClass::Bearoff
+        );
+    }
+
+    #[test]
+    fn routes_by_class() {
+        // Every roll bears off the last checker, so the 1-ply search wins for sure.
+        let certain_win = PlyEvaluator::new(RandomEvaluator::new(), 1);
+        let phase = PhaseEvaluator::new(RandomEvaluator::new(), OffEvaluator, certain_win);
+        let race = bpos!(x 12:1; o 13:1);
+        let bearoff = bpos!(x 1:1; o 24:1);
+
+        assert_eq!(phase.eval(&race), OffEvaluator.eval(&race));
+        assert_eq!(phase.eval(&bearoff).win_normal, 1.0);
+
+        let batch = phase.eval_batch(&[bearoff, race, Backgammon::new()]);
+        assert_eq!(batch[0].win_normal, 1.0);
+        assert_eq!(batch[1], OffEvaluator.eval(&race));
+    }
+}
@@ -2,23 +2,42 @@ use std::marker::PhantomData;
 
 use crate::probabilities::Probabilities;
 
-use super::Evaluator;
-use bkgm::{dice::ALL_21, State};
+use super::{Budget, Evaluator, MoveFilter, PartialEvaluator};
+use bkgm::{
+    dice::ALL_21,
+    GameState::{GameOver, Ongoing},
+    State,
+};
 
+/// N-ply expectimax search on top of another `Evaluator`.
+///
+/// At 0-ply the position is evaluated directly by the inner evaluator. At N-ply all 21 rolls are
+/// considered and for each roll the best move is chosen by an (N-1)-ply evaluation of every
+/// resulting position. The results are averaged, weighted by how often each roll occurs.
+///
+/// With a `MoveFilter` only the candidates surviving a 0-ply ranking are searched deeper.
 pub struct PlyEvaluator<E: Evaluator<G>, G: State> {
     evaluator: E,
-    #[allow(dead_code)]
     depth: usize,
+    filter: Option<MoveFilter>,
     phantom: PhantomData<G>,
 }
 
+impl<E: Evaluator<G>, G: State> PartialEvaluator<G> for PlyEvaluator<E, G> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        let probs = self.eval(pos);
+        probs.equity()
+    }
+
+    fn name(&self) -> String {
+        format!("{}-ply {}", self.depth, self.evaluator.name())
+    }
+}
+
 impl<E: Evaluator<G>, G: State> Evaluator<G> for PlyEvaluator<E, G> {
     fn eval(&self, pos: &G) -> Probabilities {
-        match pos.game_state() {
-            bkgm::GameState::Ongoing => self.ply(pos),
-            bkgm::GameState::GameOver(result) => result.value(),
-        };
-        todo!()
+        self.bounded_ply(pos, self.depth, &mut Budget::unlimited())
+            .unwrap()
     }
 }
 
@@ -27,17 +46,182 @@ impl<E: Evaluator<G>, G: State> PlyEvaluator<E, G> {
         Self {
             evaluator,
             depth,
+            filter: None,
             phantom: PhantomData,
         }
     }
 
-    // TODO: Implement N ply
-    pub fn ply(&self, pos: &G) -> f32 {
-        let mut score = 0.0;
+    /// Restricts the search at every ply above 0 to the moves surviving `filter`.
+    pub fn with_filter(mut self, filter: MoveFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Probabilities from the view of the player on roll in `pos`, searched `depth` plies deep.
+    /// Returns `None` as soon as `budget` is exhausted.
+    pub(crate) fn bounded_ply(
+        &self,
+        pos: &G,
+        depth: usize,
+        budget: &mut Budget,
+    ) -> Option<Probabilities> {
+        match pos.game_state() {
+            GameOver(result) => Some(Probabilities::from_result(&result)),
+            Ongoing if depth == 0 => {
+                if !budget.spend(1) {
+                    return None;
+                }
+                Some(self.evaluator.eval(pos))
+            }
+            Ongoing => {
+                let mut rolls = Vec::with_capacity(ALL_21.len());
+                for (dice, n) in ALL_21 {
+                    let children = match self.filter {
+                        Some(filter) if depth > 1 => {
+                            let children = pos.possible_positions(&dice);
+                            if !budget.spend(children.len()) {
+                                return None;
+                            }
+                            filter.filter(&self.evaluator, children)
+                        }
+                        _ => pos.possible_positions(&dice),
+                    };
+                    // Resulting positions are seen from the opponent, so their best reply is
+                    // our best move: the child with the lowest equity.
+                    let best = self
+                        .bounded_ply_batch(&children, depth - 1, budget)?
+                        .into_iter()
+                        .min_by(|a, b| a.equity().total_cmp(&b.equity()))
+                        .unwrap();
+                    rolls.push((best.flip(), n));
+                }
+                Some(Probabilities::weighted_average(&rolls))
+            }
+        }
+    }
+
+    /// Like `bounded_ply` for several positions, leaves are evaluated in a single batch.
+    pub(crate) fn bounded_ply_batch(
+        &self,
+        positions: &[G],
+        depth: usize,
+        budget: &mut Budget,
+    ) -> Option<Vec<Probabilities>> {
+        if depth > 0 {
+            return positions
+                .iter()
+                .map(|pos| self.bounded_ply(pos, depth, budget))
+                .collect();
+        }
+        let ongoing: Vec<G> = positions
+            .iter()
+            .filter(|pos| pos.game_state() == Ongoing)
+            .copied()
+            .collect();
+        if !budget.spend(ongoing.len()) {
+            return None;
+        }
+        let mut evaluated = self.evaluator.eval_batch(&ongoing).into_iter();
+        let probabilities = positions
+            .iter()
+            .map(|pos| match pos.game_state() {
+                GameOver(result) => Probabilities::from_result(&result),
+                Ongoing => evaluated.next().unwrap(),
+            })
+            .collect();
+        Some(probabilities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{
+        Evaluator, MoveFilter, OffEvaluator, PartialEvaluator, PlyEvaluator, RandomEvaluator,
+    };
+    use crate::probabilities::Probabilities;
+    use crate::test_utils::{assert_close, race_position};
+    use bkgm::{bpos, dice::ALL_21, Backgammon, State};
+
+    /// The winning chance is the share of checkers the opponent still has on the board. Unlike
+    /// `OffEvaluator`, it tells apart the positions after each move, which are seen from the
+    /// opponent, by the checkers the move bore off.
+    struct OpponentOffEvaluator;
+
+    impl<G: State> PartialEvaluator<G> for OpponentOffEvaluator {
+        fn try_eval(&self, pos: &G) -> f32 {
+            self.eval(pos).equity()
+        }
+    }
+
+    impl<G: State> Evaluator<G> for OpponentOffEvaluator {
+        fn eval(&self, pos: &G) -> Probabilities {
+            let lose = pos.o_off() as f32 / 15.0;
+            Probabilities {
+                win_normal: 1.0 - lose,
+                lose_normal: lose,
+                ..Probabilities::empty()
+            }
+        }
+    }
+
+    #[test]
+    fn one_ply_every_roll_wins() {
+        let ply = PlyEvaluator::new(RandomEvaluator::new(), 1);
+        let pos = bpos!(x 1:1; o 24:1);
+
+        let probabilities = ply.eval(&pos);
+        assert_eq!(probabilities.win_normal, 1.0);
+        assert_eq!(probabilities.equity(), 1.0);
+    }
+
+    #[test]
+    fn two_ply_every_roll_wins() {
+        // Even 1-1 bears off the last checker, the opponent never gets to roll.
+        let ply = PlyEvaluator::new(RandomEvaluator::new(), 2);
+        let pos = bpos!(x 2:1; o 23:1);
+
+        let probabilities = ply.eval(&pos);
+        assert_eq!(probabilities.win_normal, 1.0);
+    }
+
+    #[test]
+    fn filtered_search_every_roll_wins() {
+        let ply = PlyEvaluator::new(RandomEvaluator::new(), 2).with_filter(MoveFilter::TINY);
+        let pos = bpos!(x 2:1; o 23:1);
+
+        let probabilities = ply.eval(&pos);
+        assert_eq!(probabilities.win_normal, 1.0);
+    }
+
+    /// 1-ply equity of `pos` written out: the best move of each roll is the position with the
+    /// lowest equity for the opponent, the rolls are weighted by how often they occur.
+    fn expectimax(evaluator: &impl Evaluator<Backgammon>, pos: &Backgammon) -> f32 {
+        let mut equity = 0.0;
         for (dice, n) in ALL_21 {
-            let best = self.evaluator.best_position(pos, &dice);
-            // score += n * self.evaluator.eval(&best);
+            let best = pos
+                .possible_positions(&dice)
+                .iter()
+                .map(|child| evaluator.eval(child))
+                .min_by(|a, b| a.equity().total_cmp(&b.equity()))
+                .unwrap();
+            equity += best.flip().equity() * n as f32 / 36.0;
         }
-        score / 36.0
+        equity
+    }
+
+    #[test]
+    fn one_ply_is_expectimax() {
+        let pos = race_position();
+        let ply = PlyEvaluator::new(OffEvaluator, 1);
+        assert_close(ply.eval(&pos).equity(), expectimax(&OffEvaluator, &pos));
+        let ply = PlyEvaluator::new(OpponentOffEvaluator, 1);
+        assert_close(
+            ply.eval(&pos).equity(),
+            expectimax(&OpponentOffEvaluator, &pos),
+        );
     }
 }
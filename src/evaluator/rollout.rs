@@ -1,52 +1,285 @@
 use std::marker::PhantomData;
 
-use crate::dice::{DiceGen, FastrandDice};
+use crate::dice::{mix_seed, DiceGen, FastrandDice};
 use crate::evaluator::{Evaluator, PartialEvaluator, RandomEvaluator};
-use crate::probabilities::Probabilities;
+use crate::probabilities::{Probabilities, ResultCounter};
 use bkgm::State;
 use bkgm::{
-    dice::ALL_1296,
     Dice, GameResult,
     GameState::{GameOver, Ongoing},
 };
 use rayon::prelude::*;
+use std::hash::Hash;
 
-pub struct RolloutEvaluator<E: Evaluator<G>, G: State> {
+/// How many games a `RolloutEvaluator` plays and how their dice are chosen.
+///
+/// The first `stratified` rolls are not random: all 36 rolls occur equally often, like the first
+/// two rolls of the 1296 trials of the default settings. Turn `k` is only stratified if `trials`
+/// is a multiple of 36^(k+1), otherwise it and all later rolls are random.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RolloutSettings {
+    trials: usize,
+    stratified: usize,
+    /// Rotates the stratified rolls between trials like gnubg's quasi-random dice. Without
+    /// rotation, consecutive trials share their later stratified rolls.
+    rotate: bool,
+    /// Seed of the random dice, random if `None`.
+    seed: Option<u64>,
+}
+
+impl Default for RolloutSettings {
+    /// 1296 trials, the first two rolls stratified.
+    fn default() -> Self {
+        Self {
+            trials: 1296,
+            stratified: 2,
+            rotate: false,
+            seed: None,
+        }
+    }
+}
+
+impl RolloutSettings {
+    pub fn with_trials(mut self, trials: usize) -> Self {
+        assert!(trials > 0, "A rollout needs at least one trial");
+        self.trials = trials;
+        self
+    }
+
+    pub fn with_stratified(mut self, stratified: usize) -> Self {
+        self.stratified = stratified;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotate: bool) -> Self {
+        self.rotate = rotate;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn trials(&self) -> usize {
+        self.trials
+    }
+
+    pub fn stratified(&self) -> usize {
+        self.stratified
+    }
+
+    pub fn rotate(&self) -> bool {
+        self.rotate
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Number of rolls which are actually stratified, given the number of trials.
+    pub fn stratified_turns(&self) -> usize {
+        (0..self.stratified)
+            .take_while(|&turn| {
+                let block = 36usize.checked_pow(turn as u32 + 1);
+                matches!(block, Some(block) if self.trials % block == 0)
+            })
+            .count()
+    }
+
+    /// Stratified rolls of `trial` as indices of the 36 rolls, one for each stratified turn. Each
+    /// turn has its own permutation, with rotation the roll of a turn is also shifted by the
+    /// earlier ones.
+    fn stratified_rolls(&self, trial: usize, permutations: &[[usize; 36]]) -> Vec<usize> {
+        let mut rest = trial;
+        let mut shift = 0;
+        permutations
+            .iter()
+            .map(|permutation| {
+                let digit = rest % 36;
+                rest /= 36;
+                let index = permutation[(digit + shift) % 36];
+                if self.rotate {
+                    shift += digit;
+                }
+                index
+            })
+            .collect()
+    }
+
+    /// Permutations for `stratified_rolls`, random with rotation and the identity otherwise.
+    fn permutations(&self, rng: &mut fastrand::Rng) -> Vec<[usize; 36]> {
+        (0..self.stratified_turns())
+            .map(|_| {
+                let mut permutation: [usize; 36] = std::array::from_fn(|i| i);
+                if self.rotate {
+                    rng.shuffle(&mut permutation);
+                }
+                permutation
+            })
+            .collect()
+    }
+
+    /// Settings for rolling out `pos`. The seed is mixed with the position, otherwise all
+    /// positions of a batch would get the same dice and share the errors of their rollouts.
+    fn for_position<G: Hash>(&self, pos: &G) -> Self {
+        Self {
+            seed: self.seed.map(|seed| mix_seed(seed, pos)),
+            ..*self
+        }
+    }
+
+    /// Random dice for `trial`, after the stratified ones.
+    fn dice_gen(&self, trial: usize) -> FastrandDice {
+        match self.seed {
+            Some(seed) => FastrandDice::with_seed(seed.wrapping_add(trial as u64)),
+            None => FastrandDice::new(),
+        }
+    }
+}
+
+/// Outcome of a rollout, how often each result occurred.
+///
+/// The standard errors treat the trials as independent. Stratified dice reduce the variance, so
+/// for stratified rollouts they are upper bounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RolloutResult {
+    pub results: ResultCounter,
+}
+
+impl RolloutResult {
+    pub fn new(results: ResultCounter) -> Self {
+        Self { results }
+    }
+
+    pub fn trials(&self) -> u32 {
+        self.results.sum()
+    }
+
+    pub fn probabilities(&self) -> Probabilities {
+        Probabilities::from(&self.results)
+    }
+
+    /// Mean cubeless equity of the trials.
+    pub fn equity(&self) -> f32 {
+        self.probabilities().equity()
+    }
+
+    /// Standard error of the mean equity.
+    pub fn equity_error(&self) -> f32 {
+        // Equities of the six results, in the order of `Probabilities::to_slice`
+        let values = [1.0, 2.0, 3.0, -1.0, -2.0, -3.0];
+        let mean = self.equity();
+        let variance: f32 = self
+            .probabilities()
+            .to_slice()
+            .iter()
+            .zip(values)
+            .map(|(p, value)| p * (value - mean).powi(2))
+            .sum();
+        (variance / self.trials() as f32).sqrt()
+    }
+
+    /// `mean ± z` standard errors, e.g. `z = 1.96` for a 95% confidence interval.
+    pub fn equity_interval(&self, z: f32) -> (f32, f32) {
+        let mean = self.equity();
+        let error = z * self.equity_error();
+        (mean - error, mean + error)
+    }
+
+    /// Standard errors of the six probabilities, in the order of `Probabilities::to_slice`.
+    pub fn probability_errors(&self) -> [f32; 6] {
+        self.probabilities().to_slice().map(|p| self.error_of(p))
+    }
+
+    /// Standard errors of the five gnubg outputs, in the order of `Probabilities::to_gnu`.
+    pub fn gnu_errors(&self) -> [f32; 5] {
+        self.probabilities().to_gnu().map(|p| self.error_of(p))
+    }
+
+    /// Result from the view of the opponent.
+    pub fn flip(&self) -> Self {
+        Self::new(self.results.flip())
+    }
+
+    /// Standard error of a share `p` of the trials.
+    fn error_of(&self, p: f32) -> f32 {
+        (p * (1.0 - p) / self.trials() as f32).sqrt()
+    }
+}
+
+pub struct RolloutEvaluator<E: PartialEvaluator<G>, G: State> {
     evaluator: E,
+    settings: RolloutSettings,
     phantom: PhantomData<G>,
 }
 
-impl<E: Evaluator<G> + Sync, G: State> PartialEvaluator<G> for RolloutEvaluator<E, G> {
+impl<E: PartialEvaluator<G> + Sync, G: State + Hash> PartialEvaluator<G>
+    for RolloutEvaluator<E, G>
+{
     fn try_eval(&self, pos: &G) -> f32 {
         let probs = self.eval(pos);
         probs.equity()
     }
+
+    fn name(&self) -> String {
+        format!("Rollout {}", self.evaluator.name())
+    }
 }
 
-impl<E: Evaluator<G> + Sync, G: State> Evaluator<G> for RolloutEvaluator<E, G> {
-    /// Rolls out 1296 times, first two half moves are given, rest is random
+impl<E: PartialEvaluator<G> + Sync, G: State + Hash> Evaluator<G> for RolloutEvaluator<E, G> {
+    /// Plays `trials` games as set in the `RolloutSettings`, by default 1296 with the first two
+    /// rolls stratified and the rest random.
     fn eval(&self, pos: &G) -> Probabilities {
+        self.rollout(pos).probabilities()
+    }
+
+    /// Rolls out all positions in parallel.
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        self.rollout_batch(positions)
+            .iter()
+            .map(|result| result.probabilities())
+            .collect()
+    }
+}
+
+impl<E: PartialEvaluator<G> + Sync, G: State + Hash> RolloutEvaluator<E, G> {
+    /// Like `eval`, but keeps the counts of the results to judge how precise the rollout is.
+    pub fn rollout(&self, pos: &G) -> RolloutResult {
         debug_assert!(pos.game_state() == Ongoing);
-        let dice = ALL_1296;
+        let settings = &self.settings.for_position(pos);
+        let mut rng = match settings.seed {
+            Some(seed) => fastrand::Rng::with_seed(seed),
+            None => fastrand::Rng::new(),
+        };
+        let permutations = settings.permutations(&mut rng);
 
-        let game_results: Vec<GameResult> = dice
-            .par_iter()
-            .map(|dice_pair| {
-                let mut dice_gen = FastrandDice::new();
-                self.single_rollout(pos, &[dice_pair.0, dice_pair.1], &mut dice_gen)
+        let results = (0..settings.trials)
+            .into_par_iter()
+            .map(|trial| {
+                let first_dice: Vec<Dice> = settings
+                    .stratified_rolls(trial, &permutations)
+                    .into_iter()
+                    .map(|index| Dice::new(index / 6 + 1, index % 6 + 1))
+                    .collect();
+                self.single_rollout(pos, &first_dice, &mut settings.dice_gen(trial))
             })
-            .collect();
-
-        let mut results = [0; 6];
-        for gr in game_results {
-            results[gr as usize] += 1;
-        }
+            .fold(ResultCounter::default, |mut counter, result| {
+                counter.add(result);
+                counter
+            })
+            .reduce(ResultCounter::default, |a, b| a.combine(&b));
         debug_assert_eq!(
-            results.iter().sum::<u32>(),
-            6 * 6 * 6 * 6,
-            "Rollout should look at 1296 games"
+            results.sum() as usize,
+            settings.trials,
+            "Rollout should look at every trial"
         );
-        Probabilities::new(&results)
+        RolloutResult::new(results)
+    }
+
+    /// Rolls out all positions in parallel.
+    pub fn rollout_batch(&self, positions: &[G]) -> Vec<RolloutResult> {
+        positions.par_iter().map(|pos| self.rollout(pos)).collect()
     }
 }
 
@@ -56,14 +289,24 @@ impl<G: State> RolloutEvaluator<RandomEvaluator, G> {
     }
 }
 
-impl<E: Evaluator<G>, G: State> RolloutEvaluator<E, G> {
+impl<E: PartialEvaluator<G>, G: State> RolloutEvaluator<E, G> {
     pub fn with_evaluator(evaluator: E) -> Self {
         Self {
             evaluator,
+            settings: RolloutSettings::default(),
             phantom: PhantomData,
         }
     }
 
+    pub fn with_settings(mut self, settings: RolloutSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn settings(&self) -> &RolloutSettings {
+        &self.settings
+    }
+
     /// `first_dice` contains the dice for first moves, starting at index 0. It may be empty.
     /// Once all of those given dice have been used, subsequent dice are generated from `dice_gen`.
     #[allow(dead_code)]
@@ -102,9 +345,45 @@ impl<E: Evaluator<G>, G: State> RolloutEvaluator<E, G> {
 #[cfg(test)]
 mod tests {
     use crate::evaluator::Evaluator;
-    use crate::evaluator::RolloutEvaluator;
+    use crate::evaluator::{RolloutEvaluator, RolloutResult, RolloutSettings};
+    use crate::probabilities::ResultCounter;
+    use crate::test_utils::assert_close;
     use bkgm::{bpos, Backgammon};
 
+    #[test]
+    fn rollout_counts_every_trial() {
+        let settings = RolloutSettings::default().with_trials(72);
+        let rollout_eval = RolloutEvaluator::new_random().with_settings(settings);
+        // Every roll bears off the last checker.
+        let pos = bpos!(x 1:1; o 24:1);
+
+        let result = rollout_eval.rollout(&pos);
+        assert_eq!(result.results, ResultCounter::new(72, 0, 0, 0, 0, 0));
+        assert_eq!(result.trials(), 72);
+        assert_eq!(result.equity(), 1.0);
+        assert_eq!(result.equity_error(), 0.0);
+    }
+
+    #[test]
+    fn standard_errors() {
+        // 3 normal wins and a lost gammon
+        let result = RolloutResult::new(ResultCounter::new(3, 0, 0, 0, 1, 0));
+        assert_close(result.equity(), 0.25);
+        // Variance 0.75 * 0.75^2 + 0.25 * 2.25^2 = 1.6875 of 4 trials
+        assert_close(result.equity_error(), (1.6875f32 / 4.0).sqrt());
+        let (low, high) = result.equity_interval(2.0);
+        assert_close(high - low, 4.0 * result.equity_error());
+
+        let share = (0.75f32 * 0.25 / 4.0).sqrt();
+        let errors = result.probability_errors();
+        assert_eq!(errors, [share, 0.0, 0.0, 0.0, share, 0.0]);
+        assert_eq!(result.gnu_errors(), [share, 0.0, 0.0, share, 0.0]);
+
+        let flipped = result.flip();
+        assert_close(flipped.equity(), -0.25);
+        assert_close(flipped.equity_error(), result.equity_error());
+    }
+
     #[test]
     fn correct_results_after_first_or_second_half_move() {
         let rollout_eval = RolloutEvaluator::new_random();
@@ -156,8 +435,73 @@ mod tests {
 #[cfg(test)]
 mod private_tests {
     use crate::dice::{DiceGenMock, FastrandDice};
-    use crate::evaluator::RolloutEvaluator;
+    use crate::evaluator::{Evaluator, OffEvaluator, RolloutEvaluator, RolloutSettings};
+    use crate::test_utils::race_position;
     use bkgm::{bpos, Backgammon, Dice, GameResult};
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn stratified_turns_need_full_blocks() {
+        let settings = RolloutSettings::default();
+        assert_eq!(settings.stratified_turns(), 2);
+        assert_eq!(settings.with_trials(72).stratified_turns(), 1);
+        assert_eq!(settings.with_trials(1000).stratified_turns(), 0);
+        assert_eq!(settings.with_stratified(3).stratified_turns(), 2);
+    }
+
+    #[test]
+    fn stratified_rolls_are_balanced() {
+        for rotate in [false, true] {
+            let settings = RolloutSettings::default().with_rotation(rotate);
+            let permutations = settings.permutations(&mut fastrand::Rng::with_seed(3));
+            let mut first = HashMap::new();
+            let mut pairs = HashSet::new();
+            for trial in 0..1296 {
+                let rolls = settings.stratified_rolls(trial, &permutations);
+                *first.entry(rolls[0]).or_insert(0) += 1;
+                pairs.insert((rolls[0], rolls[1]));
+            }
+            // Every roll starts 36 trials and every pair of rolls occurs once.
+            assert_eq!(first.len(), 36);
+            assert!(first.values().all(|&n| n == 36));
+            assert_eq!(pairs.len(), 1296);
+        }
+
+        // Without rotation consecutive trials share their second roll.
+        let settings = RolloutSettings::default();
+        let permutations = settings.permutations(&mut fastrand::Rng::new());
+        assert_eq!(settings.stratified_rolls(37, &permutations), vec![1, 1]);
+    }
+
+    #[test]
+    fn seeded_rollouts_are_reproducible() {
+        let settings = RolloutSettings::default()
+            .with_trials(72)
+            .with_rotation(true)
+            .with_seed(5);
+        let rollout = RolloutEvaluator::with_evaluator(OffEvaluator).with_settings(settings);
+        let pos = race_position();
+
+        assert_eq!(rollout.eval(&pos), rollout.eval(&pos));
+    }
+
+    #[test]
+    fn positions_get_their_own_dice() {
+        let settings = RolloutSettings::default().with_seed(5);
+        let pos1 = race_position();
+        let pos2: Backgammon = bpos!(x 6:1, 5:2, 4:2; o 19:2, 20:3);
+
+        let seed1 = settings.for_position(&pos1).seed;
+        assert_eq!(seed1, settings.for_position(&pos1).seed);
+        assert_ne!(seed1, settings.for_position(&pos2).seed);
+        assert_eq!(RolloutSettings::default().for_position(&pos1).seed, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "A rollout needs at least one trial")]
+    fn rejects_zero_trials() {
+        RolloutSettings::default().with_trials(0);
+    }
 
     #[test]
     fn single_rollout_win_normal() {
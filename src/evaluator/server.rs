@@ -0,0 +1,167 @@
+use crate::evaluator::{equity_batch, Evaluator, PartialEvaluator};
+use crate::probabilities::Probabilities;
+use bkgm::State;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Positions sent by one caller, answered on `response` in the same order.
+struct Request<G> {
+    positions: Vec<G>,
+    response: SyncSender<Vec<Probabilities>>,
+}
+
+/// Shares one evaluator between many threads and answers their requests with batched runs.
+///
+/// A worker thread owns the wrapped evaluator. It waits for the first request, then keeps
+/// collecting requests from other threads until `max_batch` positions are queued or `max_wait`
+/// has passed, and evaluates all of them with a single `eval_batch` call. This is most useful for
+/// neural nets like `OnnxEvaluator` and `WildbgEvaluator` inside parallel rollouts and duels.
+pub struct InferenceServer<G: State> {
+    sender: Option<SyncSender<Request<G>>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<G: State> PartialEvaluator<G> for InferenceServer<G> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        let probs = self.eval(pos);
+        probs.equity()
+    }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        equity_batch(self, positions)
+    }
+}
+
+impl<G: State> Evaluator<G> for InferenceServer<G> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.request(vec![*pos]).pop().unwrap()
+    }
+
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        self.request(positions.to_vec())
+    }
+}
+
+impl<G: State + Send + 'static> InferenceServer<G> {
+    pub const DEFAULT_MAX_BATCH: usize = 256;
+    pub const DEFAULT_MAX_WAIT: Duration = Duration::from_micros(500);
+
+    pub fn new<E: Evaluator<G> + Send + 'static>(evaluator: E) -> Self {
+        Self::with_limits(evaluator, Self::DEFAULT_MAX_BATCH, Self::DEFAULT_MAX_WAIT)
+    }
+
+    /// `max_batch` is the number of positions after which a batch is run without waiting any
+    /// longer, `max_wait` is how long the first request of a batch waits for others to join.
+    pub fn with_limits<E: Evaluator<G> + Send + 'static>(
+        evaluator: E,
+        max_batch: usize,
+        max_wait: Duration,
+    ) -> Self {
+        let (sender, requests) = mpsc::sync_channel(max_batch.max(1));
+        let worker = thread::spawn(move || serve(evaluator, requests, max_batch, max_wait));
+        Self {
+            sender: Some(sender),
+            worker: Some(worker),
+        }
+    }
+}
+
+impl<G: State> InferenceServer<G> {
+    fn request(&self, positions: Vec<G>) -> Vec<Probabilities> {
+        if positions.is_empty() {
+            return vec![];
+        }
+        let (response, receiver) = mpsc::sync_channel(1);
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send(Request {
+                positions,
+                response,
+            })
+            .expect("Inference server stopped");
+        receiver.recv().expect("Inference server stopped")
+    }
+}
+
+impl<G: State> Drop for InferenceServer<G> {
+    fn drop(&mut self) {
+        // Closing the channel lets the worker finish its last batch and return.
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn serve<E: Evaluator<G>, G: State>(
+    evaluator: E,
+    requests: Receiver<Request<G>>,
+    max_batch: usize,
+    max_wait: Duration,
+) {
+    while let Ok(first) = requests.recv() {
+        let deadline = Instant::now() + max_wait;
+        let mut size = first.positions.len();
+        let mut batch = vec![first];
+        while size < max_batch {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match requests.recv_timeout(timeout) {
+                Ok(request) => {
+                    size += request.positions.len();
+                    batch.push(request);
+                }
+                Err(_) => break,
+            }
+        }
+
+        let positions: Vec<G> = batch
+            .iter()
+            .flat_map(|request| request.positions.iter().copied())
+            .collect();
+        let mut results = evaluator.eval_batch(&positions).into_iter();
+        for request in batch {
+            let probs = results.by_ref().take(request.positions.len()).collect();
+            // The caller only goes away if its thread panicked, there is nobody to answer then.
+            let _ = request.response.send(probs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{Evaluator, InferenceServer, OffEvaluator};
+    use crate::probabilities::Probabilities;
+    use bkgm::{bpos, Backgammon};
+    use rayon::prelude::*;
+
+    #[test]
+    fn answers_requests_from_many_threads() {
+        let server = InferenceServer::new(OffEvaluator);
+        let positions = [
+            bpos!(x 1:1; o 24:1),
+            bpos!(x 1:2; o 24:1),
+            bpos!(x 1:3; o 24:1),
+            bpos!(x 1:4; o 24:1),
+        ];
+
+        let all: Vec<Backgammon> = positions.iter().cycle().take(256).copied().collect();
+        let results: Vec<Probabilities> = all.par_iter().map(|pos| server.eval(pos)).collect();
+        for (pos, probs) in all.iter().zip(results) {
+            assert_eq!(probs, OffEvaluator.eval(pos));
+        }
+    }
+
+    #[test]
+    fn batch_keeps_order() {
+        let server = InferenceServer::new(OffEvaluator);
+        let positions = [bpos!(x 1:5; o 24:1), bpos!(x 1:1; o 24:1)];
+
+        let results = server.eval_batch(&positions);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0], OffEvaluator.eval(&positions[0]));
+        assert_eq!(results[1], OffEvaluator.eval(&positions[1]));
+        assert!(server.eval_batch(&[]).is_empty());
+    }
+}
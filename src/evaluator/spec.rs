@@ -0,0 +1,289 @@
+use crate::error::{Error, Result};
+use crate::evaluator::{
+    DynEvaluator, DynPartialEvaluator, HyperEvaluator, MctsEvaluator, NNEvaluator, NoisyEvaluator,
+    OnnxEvaluator, PlyEvaluator, PubEval, RandomEvaluator, RolloutEvaluator, SkillLevel,
+    StarEvaluator, WildbgEvaluator,
+};
+use bkgm::{Backgammon, Hypergammon, State};
+use std::fmt;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const ONNX_MODEL_PATH: &str = "model/staffa.onnx";
+const HYPER_DB_PATH: &str = "data/hyper.db";
+
+/// Evaluator chosen at runtime, e.g. from a command line argument.
+///
+/// Specs are written as `pubeval`, `random`, `onnx:model/staffa.onnx`, `wildbg:path`,
+/// `hyper:data/hyper.db`, `ply(2,onnx:path)`, `star(3,onnx:path)`, `mcts(1000,hyper)`,
+/// `rollout(pubeval)` or `noisy(beginner,pubeval)`. `star` is a pruned `ply` search, see
+/// `StarEvaluator`. `mcts` takes the number of iterations per search.
+/// The paths are optional, `onnx`, `wildbg` and `hyper` alone load their default files.
+/// `noisy` takes a `SkillLevel` or the standard deviation of the noise, and optionally a seed
+/// as third argument, e.g. `noisy(casual,pubeval,7)`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EvaluatorSpec {
+    PubEval,
+    Random,
+    Onnx(PathBuf),
+    Wildbg(PathBuf),
+    Hyper(PathBuf),
+    Ply(usize, Box<EvaluatorSpec>),
+    Star(usize, Box<EvaluatorSpec>),
+    Mcts(usize, Box<EvaluatorSpec>),
+    Rollout(Box<EvaluatorSpec>),
+    /// Noise, seed and the evaluator the noise is added to.
+    Noisy(f32, Option<u64>, Box<EvaluatorSpec>),
+}
+
+/// Games for which evaluators can be built from an `EvaluatorSpec`.
+pub trait SpecGame: State + Hash + Eq + Send + Sync + 'static {
+    /// Evaluator for the `hyper` spec. There is only a database for Hypergammon.
+    fn hyper_evaluator(path: &Path) -> Result<Box<dyn DynEvaluator<Self>>>;
+}
+
+impl SpecGame for Backgammon {
+    fn hyper_evaluator(_path: &Path) -> Result<Box<dyn DynEvaluator<Self>>> {
+        Err(Error::InvalidSpec(
+            "hyper is only available for Hypergammon".to_string(),
+        ))
+    }
+}
+
+impl SpecGame for Hypergammon {
+    fn hyper_evaluator(path: &Path) -> Result<Box<dyn DynEvaluator<Self>>> {
+        Ok(Box::new(HyperEvaluator::from_file(path)?))
+    }
+}
+
+impl EvaluatorSpec {
+    /// Builds an evaluator which only needs to rank positions, e.g. for playing.
+    pub fn build_partial<G: SpecGame>(&self) -> Result<Box<dyn DynPartialEvaluator<G>>> {
+        match self {
+            EvaluatorSpec::PubEval => Ok(Box::new(PubEval::<G>::new())),
+            EvaluatorSpec::Noisy(noise, seed, spec) => {
+                let noisy = NoisyEvaluator::new(spec.build_partial::<G>()?, *noise);
+                match seed {
+                    Some(seed) => Ok(Box::new(noisy.with_seed(*seed))),
+                    None => Ok(Box::new(noisy)),
+                }
+            }
+            spec => Ok(Box::new(spec.build::<G>()?)),
+        }
+    }
+
+    /// Builds an evaluator returning probabilities, which all specs but `pubeval` and `noisy` do.
+    pub fn build<G: SpecGame>(&self) -> Result<Box<dyn DynEvaluator<G>>> {
+        match self {
+            EvaluatorSpec::PubEval => Err(Error::InvalidSpec(
+                "pubeval only ranks positions and has no probabilities".to_string(),
+            )),
+            EvaluatorSpec::Noisy(..) => Err(Error::InvalidSpec(
+                "noisy only ranks positions and has no probabilities".to_string(),
+            )),
+            EvaluatorSpec::Random => Ok(Box::new(RandomEvaluator::new())),
+            EvaluatorSpec::Onnx(path) => Ok(Box::new(OnnxEvaluator::<G>::from_file_path(path)?)),
+            EvaluatorSpec::Wildbg(path) => {
+                Ok(Box::new(WildbgEvaluator::<G>::from_file_path(path)?))
+            }
+            EvaluatorSpec::Hyper(path) => G::hyper_evaluator(path),
+            EvaluatorSpec::Ply(depth, spec) => {
+                Ok(Box::new(PlyEvaluator::new(spec.build::<G>()?, *depth)))
+            }
+            EvaluatorSpec::Star(depth, spec) => {
+                Ok(Box::new(StarEvaluator::new(spec.build::<G>()?, *depth)))
+            }
+            EvaluatorSpec::Mcts(iterations, spec) => Ok(Box::new(
+                MctsEvaluator::new(spec.build::<G>()?).with_iterations(*iterations),
+            )),
+            EvaluatorSpec::Rollout(spec) => Ok(Box::new(RolloutEvaluator::with_evaluator(
+                spec.build_partial::<G>()?,
+            ))),
+        }
+    }
+}
+
+impl FromStr for EvaluatorSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = |msg: &str| Error::InvalidSpec(format!("{} in '{}'", msg, s));
+
+        if let Some((name, args)) = s.split_once('(') {
+            let args = args
+                .strip_suffix(')')
+                .ok_or_else(|| invalid("missing ')'"))?;
+            return match name.trim() {
+                name @ ("ply" | "star" | "mcts") => {
+                    let (n, spec) = args
+                        .split_once(',')
+                        .ok_or_else(|| invalid("search needs a number and an evaluator"))?;
+                    let n = n.trim().parse().map_err(|_| invalid("expected a number"))?;
+                    let spec = Box::new(spec.parse()?);
+                    match name {
+                        "ply" => Ok(EvaluatorSpec::Ply(n, spec)),
+                        "star" => Ok(EvaluatorSpec::Star(n, spec)),
+                        _ => Ok(EvaluatorSpec::Mcts(n, spec)),
+                    }
+                }
+                "rollout" => Ok(EvaluatorSpec::Rollout(Box::new(args.parse()?))),
+                "noisy" => {
+                    let (noise, spec) = args
+                        .split_once(',')
+                        .ok_or_else(|| invalid("noisy needs a noise and an evaluator"))?;
+                    let noise = match noise.trim().parse::<f32>() {
+                        Ok(noise) => noise,
+                        Err(_) => noise.parse::<SkillLevel>()?.noise(),
+                    };
+                    // The evaluator may contain commas itself, the seed is the last argument.
+                    let seeded = spec.rsplit_once(',').and_then(|(spec, seed)| {
+                        seed.trim().parse::<u64>().ok().map(|seed| (spec, seed))
+                    });
+                    let (spec, seed) = match seeded {
+                        Some((spec, seed)) => (spec, Some(seed)),
+                        None => (spec, None),
+                    };
+                    Ok(EvaluatorSpec::Noisy(noise, seed, Box::new(spec.parse()?)))
+                }
+                _ => Err(invalid("unknown evaluator")),
+            };
+        }
+
+        let (name, path) = match s.split_once(':') {
+            Some((name, path)) => (name.trim(), Some(PathBuf::from(path.trim()))),
+            None => (s, None),
+        };
+        let path_or = |default: &str| path.clone().unwrap_or_else(|| PathBuf::from(default));
+        match name {
+            "pubeval" | "random" if path.is_some() => Err(invalid("unexpected path")),
+            "pubeval" => Ok(EvaluatorSpec::PubEval),
+            "random" => Ok(EvaluatorSpec::Random),
+            "onnx" => Ok(EvaluatorSpec::Onnx(path_or(ONNX_MODEL_PATH))),
+            "wildbg" => Ok(EvaluatorSpec::Wildbg(path_or(
+                <WildbgEvaluator<Backgammon> as NNEvaluator<Backgammon>>::MODEL_PATH,
+            ))),
+            "hyper" => Ok(EvaluatorSpec::Hyper(path_or(HYPER_DB_PATH))),
+            _ => Err(invalid("unknown evaluator")),
+        }
+    }
+}
+
+impl fmt::Display for EvaluatorSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvaluatorSpec::PubEval => write!(f, "pubeval"),
+            EvaluatorSpec::Random => write!(f, "random"),
+            EvaluatorSpec::Onnx(path) => write!(f, "onnx:{}", path.display()),
+            EvaluatorSpec::Wildbg(path) => write!(f, "wildbg:{}", path.display()),
+            EvaluatorSpec::Hyper(path) => write!(f, "hyper:{}", path.display()),
+            EvaluatorSpec::Ply(depth, spec) => write!(f, "ply({},{})", depth, spec),
+            EvaluatorSpec::Star(depth, spec) => write!(f, "star({},{})", depth, spec),
+            EvaluatorSpec::Mcts(iterations, spec) => write!(f, "mcts({},{})", iterations, spec),
+            EvaluatorSpec::Rollout(spec) => write!(f, "rollout({})", spec),
+            EvaluatorSpec::Noisy(noise, None, spec) => write!(f, "noisy({},{})", noise, spec),
+            EvaluatorSpec::Noisy(noise, Some(seed), spec) => {
+                write!(f, "noisy({},{},{})", noise, spec, seed)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::evaluator::{EvaluatorSpec, PartialEvaluator};
+    use bkgm::{Backgammon, Dice, State};
+    use std::path::PathBuf;
+
+    #[test]
+    fn parse_specs() {
+        assert_eq!(
+            "pubeval".parse::<EvaluatorSpec>().unwrap(),
+            EvaluatorSpec::PubEval
+        );
+        assert_eq!(
+            "onnx:model/staffa.onnx".parse::<EvaluatorSpec>().unwrap(),
+            EvaluatorSpec::Onnx(PathBuf::from("model/staffa.onnx"))
+        );
+        assert_eq!(
+            " ply(2, rollout(pubeval)) "
+                .parse::<EvaluatorSpec>()
+                .unwrap(),
+            EvaluatorSpec::Ply(
+                2,
+                Box::new(EvaluatorSpec::Rollout(Box::new(EvaluatorSpec::PubEval)))
+            )
+        );
+        assert_eq!(
+            "noisy(casual,pubeval)".parse::<EvaluatorSpec>().unwrap(),
+            EvaluatorSpec::Noisy(0.05, None, Box::new(EvaluatorSpec::PubEval))
+        );
+        assert_eq!(
+            "noisy(casual,ply(1,random),7)"
+                .parse::<EvaluatorSpec>()
+                .unwrap(),
+            EvaluatorSpec::Noisy(
+                0.05,
+                Some(7),
+                Box::new(EvaluatorSpec::Ply(1, Box::new(EvaluatorSpec::Random)))
+            )
+        );
+        for spec in [
+            "random",
+            "hyper:data/hyper.db",
+            "ply(1,wildbg:nets/a.onnx)",
+            "star(3,onnx:model/staffa.onnx)",
+            "mcts(500,hyper:data/hyper.db)",
+            "noisy(0.04,rollout(pubeval))",
+            "noisy(0.04,pubeval,12)",
+        ] {
+            assert_eq!(spec.parse::<EvaluatorSpec>().unwrap().to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn invalid_specs() {
+        for spec in [
+            "gnubg",
+            "ply(x,random)",
+            "ply(2)",
+            "rollout(random",
+            "random:x",
+            "noisy(genius,pubeval)",
+        ] {
+            let result = spec.parse::<EvaluatorSpec>();
+            assert!(matches!(result, Err(Error::InvalidSpec(_))), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn build_evaluators() {
+        let spec: EvaluatorSpec = "ply(1,random)".parse().unwrap();
+        let ply = spec.build::<Backgammon>().unwrap();
+        assert_eq!(ply.name(), "1-ply RandomEvaluator");
+
+        let pubeval = EvaluatorSpec::PubEval
+            .build_partial::<Backgammon>()
+            .unwrap();
+        let pos = Backgammon::new();
+        let dice = Dice::new(3, 1);
+        assert!(pos
+            .possible_positions(&dice)
+            .contains(&pubeval.best_position(&pos, &dice)));
+        assert!(EvaluatorSpec::PubEval.build::<Backgammon>().is_err());
+
+        let rollout = EvaluatorSpec::Rollout(Box::new(EvaluatorSpec::PubEval));
+        assert_eq!(
+            rollout.build::<Backgammon>().unwrap().name(),
+            "Rollout PubEval"
+        );
+
+        let hyper = EvaluatorSpec::Hyper(PathBuf::from("data/hyper.db"));
+        assert!(matches!(
+            hyper.build::<Backgammon>(),
+            Err(Error::InvalidSpec(_))
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+use std::marker::PhantomData;
+
+use crate::probabilities::Probabilities;
+
+use super::{Evaluator, PartialEvaluator};
+use bkgm::{
+    dice::ALL_21,
+    GameState::{GameOver, Ongoing},
+    State,
+};
+
+/// Cubeless equities can't be lower than losing a backgammon.
+const MIN_EQUITY: f32 = -3.0;
+/// Cubeless equities can't be higher than winning a backgammon.
+const MAX_EQUITY: f32 = 3.0;
+
+/// N-ply search like `PlyEvaluator`, pruned with Ballard's *-minimax.
+///
+/// Because equities are bounded, the rolls searched so far bound the expected equity of a chance
+/// node. Once that bound can't change the move chosen above it, the remaining rolls are cut off
+/// (Star1). With probing (Star2) the first, statically best, move of every roll is searched
+/// before the others, which gives tighter bounds and earlier cutoffs.
+///
+/// The result is the same as the one of an unfiltered `PlyEvaluator` of the same depth.
+pub struct StarEvaluator<E: Evaluator<G>, G: State> {
+    evaluator: E,
+    depth: usize,
+    probing: bool,
+    phantom: PhantomData<G>,
+}
+
+impl<E: Evaluator<G>, G: State> PartialEvaluator<G> for StarEvaluator<E, G> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        let probs = self.eval(pos);
+        probs.equity()
+    }
+
+    fn name(&self) -> String {
+        let star = if self.probing { "Star2" } else { "Star1" };
+        format!("{}-ply {} {}", self.depth, star, self.evaluator.name())
+    }
+}
+
+impl<E: Evaluator<G>, G: State> Evaluator<G> for StarEvaluator<E, G> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        // The window lies outside of the possible equities, so the root is never cut off.
+        self.chance(pos, self.depth, MIN_EQUITY - 1.0, MAX_EQUITY + 1.0)
+            .1
+    }
+}
+
+impl<E: Evaluator<G>, G: State> StarEvaluator<E, G> {
+    /// Star2 search, probing is enabled.
+    pub fn new(evaluator: E, depth: usize) -> Self {
+        Self {
+            evaluator,
+            depth,
+            probing: true,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Disabling probing gives a Star1 search.
+    pub fn with_probing(mut self, probing: bool) -> Self {
+        self.probing = probing;
+        self
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Equity and probabilities from the view of the player on roll in `pos`, searched `depth`
+    /// plies deep.
+    ///
+    /// If the equity is at most `alpha` or at least `beta`, it's only a bound and the
+    /// probabilities are meaningless.
+    fn chance(&self, pos: &G, depth: usize, alpha: f32, beta: f32) -> (f32, Probabilities) {
+        match pos.game_state() {
+            GameOver(result) => {
+                let probs = Probabilities::from_result(&result);
+                return (probs.equity(), probs);
+            }
+            Ongoing if depth == 0 => {
+                let probs = self.evaluator.eval(pos);
+                return (probs.equity(), probs);
+            }
+            Ongoing => {}
+        }
+
+        let total: f32 = ALL_21.iter().map(|(_, n)| n).sum();
+        let rolls: Vec<(Vec<G>, f32)> = ALL_21
+            .iter()
+            .map(|(dice, n)| (self.ordered(pos.possible_positions(dice), depth), n / total))
+            .collect();
+
+        // Lower bounds of the rolls, improved by probing.
+        let mut lower = vec![MIN_EQUITY; rolls.len()];
+        if self.probing {
+            let mut sum = 0.0;
+            let mut remaining = 1.0;
+            for (i, (children, p)) in rolls.iter().enumerate() {
+                remaining -= p;
+                let b = (beta - sum - MIN_EQUITY * remaining) / p;
+                let (value, _) = self.max(&children[..1], depth, MIN_EQUITY - 1.0, b);
+                lower[i] = value;
+                sum += p * value;
+                if value >= b {
+                    return (sum + MIN_EQUITY * remaining, Probabilities::empty());
+                }
+            }
+        }
+
+        let mut sum = 0.0;
+        let mut remaining = 1.0;
+        let mut lower_remaining: f32 = rolls.iter().zip(&lower).map(|((_, p), l)| p * l).sum();
+        let mut searched = Vec::with_capacity(rolls.len());
+        for (i, (children, p)) in rolls.iter().enumerate() {
+            remaining -= p;
+            lower_remaining -= p * lower[i];
+            // The window in which this roll's value still influences the result
+            let a = (alpha - sum - MAX_EQUITY * remaining) / p;
+            let b = (beta - sum - lower_remaining) / p;
+            let (value, probs) = self.max(children, depth, a, b);
+            if value <= a {
+                return (
+                    sum + p * value + MAX_EQUITY * remaining,
+                    Probabilities::empty(),
+                );
+            }
+            if value >= b {
+                return (sum + p * value + lower_remaining, Probabilities::empty());
+            }
+            sum += p * value;
+            searched.push((probs, *p));
+        }
+        (sum, Probabilities::weighted_average(&searched))
+    }
+
+    /// Best move among `children` for the player on roll, whose position is searched `depth`
+    /// plies deep. Alpha-beta search with the same meaning of the window as in `chance`.
+    fn max(&self, children: &[G], depth: usize, alpha: f32, beta: f32) -> (f32, Probabilities) {
+        if depth == 1 {
+            return self
+                .leaves(children)
+                .into_iter()
+                .map(|probs| (-probs.equity(), probs.flip()))
+                .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+                .unwrap();
+        }
+        let mut best = (f32::NEG_INFINITY, Probabilities::empty());
+        let mut alpha = alpha;
+        for child in children {
+            let (value, probs) = self.chance(child, depth - 1, -beta, -alpha);
+            if -value > best.0 {
+                best = (-value, probs.flip());
+            }
+            if best.0 >= beta {
+                break;
+            }
+            alpha = alpha.max(best.0);
+        }
+        best
+    }
+
+    /// Probabilities of the children from their own view, evaluated in a single batch.
+    fn leaves(&self, positions: &[G]) -> Vec<Probabilities> {
+        let ongoing: Vec<G> = positions
+            .iter()
+            .filter(|pos| pos.game_state() == Ongoing)
+            .copied()
+            .collect();
+        let mut evaluated = self.evaluator.eval_batch(&ongoing).into_iter();
+        positions
+            .iter()
+            .map(|pos| match pos.game_state() {
+                GameOver(result) => Probabilities::from_result(&result),
+                Ongoing => evaluated.next().unwrap(),
+            })
+            .collect()
+    }
+
+    /// Sorts the children by their static evaluation, best move first. Good moves raise alpha
+    /// early, which cuts off more of the worse ones.
+    fn ordered(&self, children: Vec<G>, depth: usize) -> Vec<G> {
+        if depth == 1 {
+            // All leaves are evaluated anyway
+            return children;
+        }
+        let values: Vec<f32> = self
+            .leaves(&children)
+            .iter()
+            .map(|probs| probs.equity())
+            .collect();
+        let mut indexed: Vec<(f32, G)> = values.into_iter().zip(children).collect();
+        indexed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        indexed.into_iter().map(|(_, child)| child).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{Evaluator, OffEvaluator, PlyEvaluator, StarEvaluator};
+    use crate::test_utils::{assert_close, race_position};
+    use bkgm::{bpos, Backgammon};
+
+    #[test]
+    fn two_ply_every_roll_wins() {
+        let star = StarEvaluator::new(OffEvaluator, 2);
+        let pos = bpos!(x 2:1; o 23:1);
+
+        let probabilities = star.eval(&pos);
+        assert_eq!(probabilities.win_normal, 1.0);
+    }
+
+    #[test]
+    fn same_as_full_search() {
+        let positions: [Backgammon; 2] = [race_position(), bpos!(x 3:2, 2:1, 1:3; o 22:2, 24:1)];
+        for depth in 1..=2 {
+            let ply = PlyEvaluator::new(OffEvaluator, depth);
+            let star1 = StarEvaluator::new(OffEvaluator, depth).with_probing(false);
+            let star2 = StarEvaluator::new(OffEvaluator, depth);
+            for pos in positions {
+                let expected = ply.eval(&pos).equity();
+                assert_close(star1.eval(&pos).equity(), expected);
+                assert_close(star2.eval(&pos).equity(), expected);
+            }
+        }
+    }
+}
@@ -1,14 +1,15 @@
-use crate::evaluator::{Evaluator, NNEvaluator, PartialEvaluator};
+use super::model::{self, Model, ModelShape};
+use crate::error::Result;
+use crate::evaluator::{equity_batch, Evaluator, NNEvaluator, PartialEvaluator};
 use crate::probabilities::Probabilities;
 use bkgm::State;
 use std::marker::PhantomData;
 use std::path::Path;
-use tract_onnx::prelude::*;
 
 #[derive(Clone)]
 pub struct WildbgEvaluator<G: State> {
-    #[allow(clippy::type_complexity)]
-    model: RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>,
+    model: Model,
+    shape: ModelShape,
     phantom: PhantomData<G>,
 }
 
@@ -17,19 +18,28 @@ impl<G: State> PartialEvaluator<G> for WildbgEvaluator<G> {
         let probs = self.eval(pos);
         probs.equity()
     }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        equity_batch(self, positions)
+    }
 }
 
 impl<G: State> Evaluator<G> for WildbgEvaluator<G> {
     fn eval(&self, position: &G) -> Probabilities {
         let output = self.output_vec(position);
-        Probabilities {
-            win_normal: output[0],
-            win_gammon: output[1],
-            win_bg: output[2],
-            lose_normal: output[3],
-            lose_gammon: output[4],
-            lose_bg: output[5],
-        }
+        model::probabilities(&output)
+    }
+
+    /// Evaluates all positions with a single run of the model.
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        let inputs = positions
+            .iter()
+            .flat_map(|pos| self.input_vec(pos))
+            .collect();
+        model::run_batch(&self.model, inputs, Self::NUM_INTPUTS)
+            .iter()
+            .map(|output| model::probabilities(output))
+            .collect()
     }
 }
 
@@ -38,20 +48,19 @@ impl<G: State> NNEvaluator<G> for WildbgEvaluator<G> {
     const NUM_INTPUTS: usize = 202;
     const NUM_OUTPUTS: usize = 6;
 
-    fn from_file_path(file_path: impl AsRef<Path>) -> Option<Self> {
-        let model = onnx()
-            .model_for_path(file_path)
-            .unwrap()
-            .into_optimized()
-            .unwrap()
-            .into_runnable()
-            .unwrap();
-        Some(Self {
+    fn from_file_path(file_path: impl AsRef<Path>) -> Result<Self> {
+        let (model, shape) = model::load(file_path, Self::NUM_INTPUTS, Self::NUM_OUTPUTS)?;
+        Ok(Self {
             model,
+            shape,
             phantom: PhantomData,
         })
     }
 
+    fn shape(&self) -> &ModelShape {
+        &self.shape
+    }
+
     fn input_labels(&self) -> Vec<String> {
         let mut labels = vec![];
         labels.push("x_off".to_string());
@@ -123,15 +132,9 @@ impl<G: State> NNEvaluator<G> for WildbgEvaluator<G> {
 
     fn output_vec(&self, position: &G) -> Vec<f32> {
         let inputs = self.input_vec(position);
-        let tract_inputs = tract_ndarray::Array1::from_vec(inputs)
-            .into_shape([1, Self::NUM_INTPUTS])
-            .unwrap();
-        let tensor = tract_inputs.into_tensor();
-
-        // run the model on the input
-        let result = self.model.run(tvec!(tensor.into())).unwrap();
-        let array_view = result[0].to_array_view::<f32>().unwrap();
-        array_view.iter().map(|&x| x).collect()
+        model::run_batch(&self.model, inputs, Self::NUM_INTPUTS)
+            .pop()
+            .unwrap()
     }
 }
 
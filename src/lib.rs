@@ -1,6 +1,16 @@
+pub mod analysis;
+pub mod cube;
 pub mod dice;
 pub mod duel;
+pub mod error;
 pub mod evaluator;
 pub mod inputs;
+pub mod met;
+pub mod notation;
+pub mod policy;
 pub mod position_finder;
 pub mod probabilities;
+pub mod search;
+
+#[cfg(test)]
+mod test_utils;
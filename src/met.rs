@@ -0,0 +1,510 @@
+use crate::dice::DiceGen;
+use crate::duel::Duel;
+use crate::error::{Error, Result};
+use crate::evaluator::PartialEvaluator;
+use crate::probabilities::{Probabilities, ResultCounter};
+use bkgm::{GameResult, State};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Default length of generated match equity tables.
+pub const DEFAULT_MET_LENGTH: usize = 25;
+
+/// Score of a match from the view of the player on roll, counted in points still needed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchScore {
+    pub away: usize,
+    pub opp_away: usize,
+    /// Whether this is the Crawford game. A 1-away score without it is post-Crawford.
+    pub crawford: bool,
+}
+
+impl MatchScore {
+    /// Score at the start of a match to `length` points.
+    pub fn new(length: usize) -> Self {
+        Self {
+            away: length,
+            opp_away: length,
+            crawford: false,
+        }
+    }
+
+    /// The same score from the view of the opponent.
+    pub fn flip(&self) -> Self {
+        Self {
+            away: self.opp_away,
+            opp_away: self.away,
+            crawford: self.crawford,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.away == 0 || self.opp_away == 0
+    }
+
+    /// Whether the Crawford game has already been played, so that the cube is available again.
+    pub fn is_post_crawford(&self) -> bool {
+        (self.away == 1 || self.opp_away == 1) && !self.crawford
+    }
+
+    /// Score after the player on roll won or lost `points`.
+    pub fn after(&self, points: usize, won: bool) -> Self {
+        let (away, opp_away) = if won {
+            (self.away.saturating_sub(points), self.opp_away)
+        } else {
+            (self.away, self.opp_away.saturating_sub(points))
+        };
+        // The first game after a player reached 1-away is the Crawford game.
+        let crawford = (away == 1 || opp_away == 1) && self.away > 1 && self.opp_away > 1;
+        Self {
+            away,
+            opp_away,
+            crawford,
+        }
+    }
+}
+
+/// Match winning chances (MWC) of a player, by the points both players still need.
+///
+/// Published tables like Kazaross-XG2 are loaded from a text file, tables tailored to a bot are
+/// calculated with `generate`. Lines starting with `#` and blank lines are ignored.
+/// A line `pre-crawford` is followed by one row per score of the player: row `i` holds the
+/// MWC of the player needing `i` points against an opponent needing 1, 2, ... points.
+/// A line `post-crawford` is followed by a single row: value `i` is the MWC of the player
+/// needing `i` points against an opponent needing 1 point after the Crawford game.
+/// All values are fractions between 0 and 1, the table has to be square.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchEquityTable {
+    /// `pre_crawford[i][j]`: the player needs `i + 1` points, the opponent `j + 1` points.
+    pre_crawford: Vec<Vec<f32>>,
+    /// `post_crawford[i]`: the player needs `i + 1` points, the opponent 1 point.
+    post_crawford: Vec<f32>,
+}
+
+impl MatchEquityTable {
+    pub fn new(pre_crawford: Vec<Vec<f32>>, post_crawford: Vec<f32>) -> Result<Self> {
+        let length = pre_crawford.len();
+        if length == 0 {
+            return Err(Error::MalformedTable("table is empty".to_string()));
+        }
+        if let Some(row) = pre_crawford.iter().position(|row| row.len() != length) {
+            return Err(Error::MalformedTable(format!(
+                "pre-Crawford row {} has {} values, expected {}",
+                row + 1,
+                pre_crawford[row].len(),
+                length
+            )));
+        }
+        if post_crawford.len() != length {
+            return Err(Error::MalformedTable(format!(
+                "post-Crawford row has {} values, expected {}",
+                post_crawford.len(),
+                length
+            )));
+        }
+        let mut values = pre_crawford.iter().flatten().chain(post_crawford.iter());
+        if let Some(value) = values.find(|v| !(0.0..=1.0).contains(*v)) {
+            return Err(Error::MalformedTable(format!(
+                "{} is not a probability",
+                value
+            )));
+        }
+        Ok(Self {
+            pre_crawford,
+            post_crawford,
+        })
+    }
+
+    /// Calculates a table up to `length` points from the given assumptions.
+    ///
+    /// Post-Crawford the trailer doubles at once, Crawford games are played without cube.
+    /// Before that a game is played for a doubled cube with a probability of `double_rate`.
+    /// Each game is won by either player with the same probability.
+    pub fn generate(length: usize, params: &MetParameters) -> Self {
+        let g = params.gammon_rate;
+        let d = params.double_rate;
+
+        // Post-Crawford each game is worth 2 points, 4 with a gammon.
+        let mut post_crawford = vec![0.0; length];
+        for i in 0..length {
+            let single = if i < 2 { 1.0 } else { post_crawford[i - 2] };
+            let gammon = if i < 4 { 1.0 } else { post_crawford[i - 4] };
+            post_crawford[i] = 0.5 * (1.0 - g) * single + 0.5 * g * gammon;
+            if i == 1 {
+                post_crawford[i] -= params.free_drop_2;
+            } else if i == 3 {
+                post_crawford[i] -= params.free_drop_4;
+            }
+        }
+
+        // Points won in a game, with their probability given that the game is won.
+        let crawford_points = [(1, 1.0 - g), (2, g)];
+        let cube_points = [
+            (1, (1.0 - d) * (1.0 - g)),
+            (2, (1.0 - d) * g + d * (1.0 - g)),
+            (4, d * g),
+        ];
+
+        // Every game reduces the points needed, so fill the table by increasing sum of both scores.
+        let mut table = Self {
+            pre_crawford: vec![vec![0.5; length]; length],
+            post_crawford,
+        };
+        for sum in 3..=2 * length {
+            for away in 1..sum {
+                let opp_away = sum - away;
+                if away > length || opp_away > length {
+                    continue;
+                }
+                let score = MatchScore {
+                    away,
+                    opp_away,
+                    crawford: away == 1 || opp_away == 1,
+                };
+                let points: &[(usize, f32)] = if score.crawford {
+                    &crawford_points
+                } else {
+                    &cube_points
+                };
+                let mwc: f32 = points
+                    .iter()
+                    .map(|&(won, p)| {
+                        let win = table.mwc(&score.after(won, true));
+                        let lose = table.mwc(&score.after(won, false));
+                        0.5 * p * (win + lose)
+                    })
+                    .sum();
+                table.pre_crawford[away - 1][opp_away - 1] = mwc;
+            }
+        }
+        table
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Err(Error::FileNotFound(path.to_path_buf()));
+        }
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Writes the table in the format read by `from_file`.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Longest match covered by the table.
+    pub fn length(&self) -> usize {
+        self.pre_crawford.len()
+    }
+
+    /// MWC of a player needing `away` points before the Crawford game or in it.
+    /// Panics if a score is 0 or exceeds the length of the table.
+    pub fn pre_crawford(&self, away: usize, opp_away: usize) -> f32 {
+        self.pre_crawford[away - 1][opp_away - 1]
+    }
+
+    /// MWC of a player needing `away` points against an opponent needing 1 point, after the
+    /// Crawford game. Panics if `away` is 0 or exceeds the length of the table.
+    pub fn post_crawford(&self, away: usize) -> f32 {
+        self.post_crawford[away - 1]
+    }
+
+    /// MWC of the player on roll at the start of a game at `score`.
+    pub fn mwc(&self, score: &MatchScore) -> f32 {
+        if score.away == 0 {
+            1.0
+        } else if score.opp_away == 0 {
+            0.0
+        } else if score.is_post_crawford() {
+            if score.opp_away == 1 {
+                self.post_crawford(score.away)
+            } else {
+                1.0 - self.post_crawford(score.opp_away)
+            }
+        } else {
+            self.pre_crawford(score.away, score.opp_away)
+        }
+    }
+
+    /// Cubeless MWC of the player on roll, if the game is played for `cube_value` points.
+    pub fn cubeless_mwc(
+        &self,
+        probs: &Probabilities,
+        score: &MatchScore,
+        cube_value: usize,
+    ) -> f32 {
+        let outcomes = [
+            (probs.win_normal, 1, true),
+            (probs.win_gammon, 2, true),
+            (probs.win_bg, 3, true),
+            (probs.lose_normal, 1, false),
+            (probs.lose_gammon, 2, false),
+            (probs.lose_bg, 3, false),
+        ];
+        outcomes
+            .iter()
+            .map(|&(p, points, won)| p * self.mwc(&score.after(points * cube_value, won)))
+            .sum()
+    }
+
+    /// Converts MWC into equity normalized to the cube value: -1 for losing `cube_value` points,
+    /// 1 for winning them. Comparable to money equities when making decisions at this score.
+    ///
+    /// If winning and losing lead to the same MWC, e.g. because the match is already over, the
+    /// game doesn't matter and the equity is 0.
+    pub fn mwc_to_equity(&self, mwc: f32, score: &MatchScore, cube_value: usize) -> f32 {
+        let win = self.mwc(&score.after(cube_value, true));
+        let lose = self.mwc(&score.after(cube_value, false));
+        if (win - lose).abs() < f32::EPSILON {
+            return 0.0;
+        }
+        2.0 * (mwc - lose) / (win - lose) - 1.0
+    }
+
+    /// Inverse of `mwc_to_equity`.
+    pub fn equity_to_mwc(&self, equity: f32, score: &MatchScore, cube_value: usize) -> f32 {
+        let win = self.mwc(&score.after(cube_value, true));
+        let lose = self.mwc(&score.after(cube_value, false));
+        lose + (equity + 1.0) / 2.0 * (win - lose)
+    }
+}
+
+impl FromStr for MatchEquityTable {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut pre_crawford = Vec::new();
+        let mut post_crawford = None;
+        // `None` until the first section header, then whether we are in the post-Crawford section.
+        let mut post_section = None;
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line {
+                "pre-crawford" => post_section = Some(false),
+                "post-crawford" => post_section = Some(true),
+                _ => {
+                    let row = line
+                        .split_whitespace()
+                        .map(|value| value.parse::<f32>())
+                        .collect::<std::result::Result<Vec<_>, _>>()
+                        .map_err(|err| {
+                            Error::MalformedTable(format!("line {}: {}", number + 1, err))
+                        })?;
+                    match post_section {
+                        Some(false) => pre_crawford.push(row),
+                        Some(true) if post_crawford.is_none() => post_crawford = Some(row),
+                        Some(true) => {
+                            return Err(Error::MalformedTable(format!(
+                                "line {}: more than one post-Crawford row",
+                                number + 1
+                            )))
+                        }
+                        None => {
+                            return Err(Error::MalformedTable(format!(
+                                "line {}: values before the first section",
+                                number + 1
+                            )))
+                        }
+                    }
+                }
+            }
+        }
+        let post_crawford = post_crawford
+            .ok_or_else(|| Error::MalformedTable("post-Crawford row is missing".to_string()))?;
+        Self::new(pre_crawford, post_crawford)
+    }
+}
+
+impl fmt::Display for MatchEquityTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# Match equity table up to {} points", self.length())?;
+        writeln!(f, "pre-crawford")?;
+        for row in &self.pre_crawford {
+            writeln!(f, "{}", format_row(row))?;
+        }
+        writeln!(f, "post-crawford")?;
+        writeln!(f, "{}", format_row(&self.post_crawford))
+    }
+}
+
+fn format_row(row: &[f32]) -> String {
+    row.iter()
+        .map(|mwc| format!("{:.6}", mwc))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Assumptions from which match equity tables are calculated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MetParameters {
+    /// Share of won games which are gammons or backgammons.
+    pub gammon_rate: f32,
+    /// Share of pre-Crawford games which are played for a doubled cube.
+    pub double_rate: f32,
+    /// MWC the leader gains from the free drop when the trailer needs 2 points.
+    pub free_drop_2: f32,
+    /// MWC the leader gains from the free drop when the trailer needs 4 points.
+    pub free_drop_4: f32,
+}
+
+impl Default for MetParameters {
+    fn default() -> Self {
+        Self {
+            gammon_rate: 0.25,
+            double_rate: 0.5,
+            free_drop_2: 0.015,
+            free_drop_4: 0.004,
+        }
+    }
+}
+
+impl MetParameters {
+    pub fn with_gammon_rate(mut self, gammon_rate: f32) -> Self {
+        self.gammon_rate = gammon_rate;
+        self
+    }
+
+    /// Default parameters, but with the gammon rate of the given game results.
+    pub fn from_results(results: &ResultCounter) -> Self {
+        let gammons = [
+            GameResult::WinGammon,
+            GameResult::WinBackgammon,
+            GameResult::LoseGammon,
+            GameResult::LoseBackgammon,
+        ]
+        .iter()
+        .map(|&result| results.num_of(result))
+        .sum::<u32>();
+        Self::default().with_gammon_rate(gammons as f32 / results.sum() as f32)
+    }
+
+    /// Default parameters, but with the gammon rate measured in `duels` duels.
+    /// Typically both evaluators of the duel are the same bot.
+    pub fn from_duel<T, U, G, V>(duel: &Duel<T, U, G>, duels: usize, dice_gen: &mut V) -> Self
+    where
+        T: PartialEvaluator<G>,
+        U: PartialEvaluator<G>,
+        G: State,
+        V: DiceGen,
+    {
+        let results = (0..duels).fold(ResultCounter::default(), |results, _| {
+            results.combine(&duel.duel(dice_gen))
+        });
+        Self::from_results(&results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::met::{MatchEquityTable, MatchScore, MetParameters, DEFAULT_MET_LENGTH};
+    use crate::probabilities::{Probabilities, ResultCounter};
+    use crate::test_utils::assert_close;
+    use bkgm::GameResult;
+
+    #[test]
+    fn score_after_game() {
+        let score = MatchScore::new(3);
+        let crawford = score.after(2, true);
+        assert_eq!(crawford.away, 1);
+        assert!(crawford.crawford);
+        assert!(!crawford.is_post_crawford());
+
+        let post_crawford = crawford.after(1, false);
+        assert_eq!(post_crawford.opp_away, 2);
+        assert!(post_crawford.is_post_crawford());
+        assert!(post_crawford.after(1, true).is_over());
+    }
+
+    #[test]
+    fn generated_table() {
+        let met = MatchEquityTable::generate(DEFAULT_MET_LENGTH, &MetParameters::default());
+        assert_close(met.pre_crawford(1, 1), 0.5);
+        assert_close(met.pre_crawford(4, 4), 0.5);
+        // The trailer only wins the Crawford game at 2-away with a gammon or two wins in a row.
+        assert_close(met.pre_crawford(2, 1), 0.5 * 0.25 + 0.5 * 0.75 * 0.5);
+        assert_close(met.post_crawford(2), 0.485);
+        for away in 1..=met.length() {
+            for opp_away in 1..=met.length() {
+                let mwc = met.pre_crawford(away, opp_away);
+                assert_close(mwc + met.pre_crawford(opp_away, away), 1.0);
+                if away < opp_away {
+                    assert!(mwc > 0.5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cubeless_mwc() {
+        let met = MatchEquityTable::generate(DEFAULT_MET_LENGTH, &MetParameters::default());
+        let score = MatchScore::new(2);
+        let win = Probabilities::from_result(&GameResult::WinNormal);
+        assert_close(met.cubeless_mwc(&win, &score, 1), met.pre_crawford(1, 2));
+        assert_close(met.cubeless_mwc(&win, &score, 2), 1.0);
+        assert_close(
+            met.mwc_to_equity(met.cubeless_mwc(&win, &score, 1), &score, 1),
+            1.0,
+        );
+        assert_close(met.equity_to_mwc(0.0, &score, 1), 0.5);
+
+        let over = score.after(2, true);
+        assert_eq!(met.mwc_to_equity(1.0, &over, 1), 0.0);
+    }
+
+    #[test]
+    fn parse_table() {
+        let text = "# 2 point match\npre-crawford\n0.5 0.7\n0.3 0.5\n\npost-crawford\n0.5 0.48\n";
+        let met: MatchEquityTable = text.parse().unwrap();
+        assert_eq!(met.length(), 2);
+        assert_close(met.pre_crawford(1, 2), 0.7);
+        assert_close(met.post_crawford(2), 0.48);
+    }
+
+    #[test]
+    fn malformed_table() {
+        let not_square = "pre-crawford\n0.5 0.7\n0.3\npost-crawford\n0.5 0.48\n";
+        let no_post = "pre-crawford\n0.5\n";
+        let not_a_number = "pre-crawford\n0.5 x\n";
+        for text in [not_square, no_post, not_a_number] {
+            let result = text.parse::<MatchEquityTable>();
+            assert!(matches!(result, Err(Error::MalformedTable(_))));
+        }
+    }
+
+    #[test]
+    fn write_and_parse() {
+        let params = MetParameters::default().with_gammon_rate(0.2);
+        let met = MatchEquityTable::generate(7, &params);
+        let parsed: MatchEquityTable = met.to_string().parse().unwrap();
+        assert_eq!(parsed.length(), 7);
+        for away in 1..=7 {
+            assert_close(parsed.post_crawford(away), met.post_crawford(away));
+            for opp_away in 1..=7 {
+                let expected = met.pre_crawford(away, opp_away);
+                assert_close(parsed.pre_crawford(away, opp_away), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn gammons_favour_the_trailer() {
+        let few = MatchEquityTable::generate(5, &MetParameters::default().with_gammon_rate(0.1));
+        let many = MatchEquityTable::generate(5, &MetParameters::default().with_gammon_rate(0.4));
+        assert!(many.pre_crawford(3, 1) > few.pre_crawford(3, 1));
+    }
+
+    #[test]
+    fn gammon_rate_from_results() {
+        let results = ResultCounter::new(5, 2, 1, 8, 3, 1);
+        let params = MetParameters::from_results(&results);
+        assert_close(params.gammon_rate, 0.35);
+    }
+}
@@ -0,0 +1,303 @@
+use bkgm::{Dice, State};
+
+const OFF: usize = 0;
+const BAR: usize = 25;
+
+/// Standard notation of the move from `pos` to `result` with `dice`, e.g. `24/20 13/8`,
+/// `bar/22* 6/off` or `8/5(2)`. Points are counted from the view of the player on roll.
+///
+/// `result` is one of the positions returned by `possible_positions`, so it is seen from the
+/// opponent. Returns `None` if `result` can't be reached with `dice` and an empty string if no
+/// checker can be moved.
+pub fn format_move<G: State>(pos: &G, dice: &Dice, result: &G) -> Option<String> {
+    let board = Board::of(pos);
+    let target = Board::of(&result.flip());
+    let orders = match dice {
+        Dice::Double(die) => vec![vec![*die; 4]],
+        Dice::Regular(dice) => vec![vec![dice.big, dice.small], vec![dice.small, dice.big]],
+    };
+    let mut steps = Vec::new();
+    let found = orders
+        .iter()
+        .any(|order| find_steps(board, order, &target, &mut steps));
+    if !found {
+        return None;
+    }
+
+    let mut moves: Vec<Vec<(usize, bool)>> = steps
+        .iter()
+        .map(|step| vec![(step.from, false), (step.to, step.hit)])
+        .collect();
+    // A checker moving on from where another one just arrived is written as one move, e.g. 13/4.
+    while let Some((i, j)) = chained(&moves) {
+        let tail = moves.remove(j);
+        let i = if j < i { i - 1 } else { i };
+        moves[i].extend_from_slice(&tail[1..]);
+    }
+    moves.sort_by(|a, b| {
+        let a = (a[0].0, a[a.len() - 1].0);
+        let b = (b[0].0, b[b.len() - 1].0);
+        b.cmp(&a)
+    });
+
+    let mut notation: Vec<(String, usize)> = Vec::new();
+    for m in moves.iter().map(|m| format_checker_move(m)) {
+        match notation.last_mut() {
+            Some((last, count)) if *last == m => *count += 1,
+            _ => notation.push((m, 1)),
+        }
+    }
+    let notation: Vec<String> = notation
+        .into_iter()
+        .map(|(m, count)| match count {
+            1 => m,
+            count => format!("{}({})", m, count),
+        })
+        .collect();
+    Some(notation.join(" "))
+}
+
+/// Resulting position, as returned by `possible_positions`, of the move written in `notation`.
+///
+/// Accepts the output of `format_move`, but also moves written in several steps like
+/// `24/20 20/15`. Hits are done automatically, a missing `*` is no error.
+/// Returns `None` if the notation can't be parsed or isn't a legal move with `dice`.
+pub fn parse_move<G: State>(pos: &G, dice: &Dice, notation: &str) -> Option<G> {
+    let mut pending = Vec::new();
+    for token in notation.split_whitespace() {
+        let token = token.to_lowercase();
+        let (token, count) = match token.strip_suffix(')') {
+            Some(token) => {
+                let (token, count) = token.split_once('(')?;
+                (token.to_string(), count.parse::<usize>().ok()?)
+            }
+            None => (token, 1),
+        };
+        let points = token
+            .split('/')
+            .map(|point| parse_point(point.trim_end_matches('*')))
+            .collect::<Option<Vec<usize>>>()?;
+        if points.len() < 2 {
+            return None;
+        }
+        for _ in 0..count {
+            pending.extend(points.windows(2).map(|w| (w[0], w[1])));
+        }
+    }
+
+    // Play the steps in any order in which a checker is available, like `20/15 24/20`.
+    let mut board = Board::of(pos);
+    while !pending.is_empty() {
+        let playable = pending.iter().position(|&(from, _)| board.x[from] > 0)?;
+        let (from, to) = pending.remove(playable);
+        board = board.play(from, to)?;
+    }
+    pos.possible_positions(dice)
+        .into_iter()
+        .find(|result| Board::of(&result.flip()) == board)
+}
+
+/// Board from the view of the player on roll.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Board {
+    /// Checkers of the player on roll, index 0 is off, 1 to 24 are points, 25 is the bar.
+    x: [u8; 26],
+    /// Checkers of the opponent on points 1 to 24, counted from the view of the player on roll.
+    o: [u8; 26],
+    o_bar: u8,
+    o_off: u8,
+}
+
+/// Single checker moved by one die.
+#[derive(Clone, Copy, Debug)]
+struct Step {
+    from: usize,
+    to: usize,
+    hit: bool,
+}
+
+impl Board {
+    fn of<G: State>(pos: &G) -> Self {
+        let mut x = [0; 26];
+        let mut o = [0; 26];
+        x[OFF] = pos.x_off();
+        x[BAR] = pos.x_bar();
+        for i in 1..=24 {
+            let pip = pos.pip(i);
+            #[allow(clippy::comparison_chain)]
+            if pip > 0 {
+                x[i] = pip as u8;
+            } else if pip < 0 {
+                o[i] = -pip as u8;
+            }
+        }
+        Board {
+            x,
+            o,
+            o_bar: pos.o_bar(),
+            o_off: pos.o_off(),
+        }
+    }
+
+    /// Moves a checker from `from` by `die` pips, if that's legal.
+    fn step(&self, from: usize, die: usize) -> Option<(Board, Step)> {
+        if self.x[from] == 0 || (self.x[BAR] > 0 && from != BAR) {
+            return None;
+        }
+        let to = if from > die {
+            from - die
+        } else {
+            let all_home = self.x[7..=BAR].iter().all(|&n| n == 0);
+            let higher = self.x[from + 1..=6].iter().any(|&n| n > 0);
+            if !all_home || (from < die && higher) {
+                return None;
+            }
+            OFF
+        };
+        let hit = to != OFF && self.o[to] == 1;
+        let board = self.play(from, to)?;
+        Some((board, Step { from, to, hit }))
+    }
+
+    /// Moves a checker from `from` to `to` regardless of the dice, hitting a blot.
+    fn play(&self, from: usize, to: usize) -> Option<Board> {
+        if self.x[from] == 0 || to >= from || (to != OFF && self.o[to] > 1) {
+            return None;
+        }
+        let mut board = *self;
+        board.x[from] -= 1;
+        board.x[to] += 1;
+        if to != OFF && board.o[to] == 1 {
+            board.o[to] = 0;
+            board.o_bar += 1;
+        }
+        Some(board)
+    }
+}
+
+/// Depth first search for steps using `dice` in order which lead from `board` to `target`.
+fn find_steps(board: Board, dice: &[usize], target: &Board, steps: &mut Vec<Step>) -> bool {
+    if board == *target {
+        return true;
+    }
+    let (die, rest) = match dice.split_first() {
+        Some((die, rest)) => (*die, rest),
+        None => return false,
+    };
+    for from in (1..=BAR).rev() {
+        if let Some((next, step)) = board.step(from, die) {
+            steps.push(step);
+            if find_steps(next, rest, target, steps) {
+                return true;
+            }
+            steps.pop();
+        }
+    }
+    false
+}
+
+/// Indices of a move ending on a point and another move starting there.
+fn chained(moves: &[Vec<(usize, bool)>]) -> Option<(usize, usize)> {
+    for (i, a) in moves.iter().enumerate() {
+        let end = a[a.len() - 1].0;
+        if end == OFF {
+            continue;
+        }
+        if let Some(j) = (0..moves.len()).find(|&j| j != i && moves[j][0].0 == end) {
+            return Some((i, j));
+        }
+    }
+    None
+}
+
+/// Writes a move like `13/9*/4`. Intermediate points are only written if a blot is hit there.
+fn format_checker_move(points: &[(usize, bool)]) -> String {
+    let mut notation = format_point(points[0].0);
+    for (i, &(point, hit)) in points.iter().enumerate().skip(1) {
+        if hit || i == points.len() - 1 {
+            notation.push('/');
+            notation.push_str(&format_point(point));
+            if hit {
+                notation.push('*');
+            }
+        }
+    }
+    notation
+}
+
+fn format_point(point: usize) -> String {
+    match point {
+        BAR => "bar".to_string(),
+        OFF => "off".to_string(),
+        point => point.to_string(),
+    }
+}
+
+fn parse_point(point: &str) -> Option<usize> {
+    match point {
+        "bar" => Some(BAR),
+        "off" => Some(OFF),
+        point => point.parse().ok().filter(|p| (1..=24).contains(p)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::notation::{format_move, parse_move};
+    use bkgm::{bpos, Backgammon, Dice, State};
+
+    fn assert_notation(pos: &Backgammon, dice: &Dice, notation: &str) {
+        let result = parse_move(pos, dice, notation).unwrap();
+        assert_eq!(format_move(pos, dice, &result).unwrap(), notation);
+    }
+
+    #[test]
+    fn opening_moves() {
+        let pos = Backgammon::new();
+        let dice = Dice::new(5, 4);
+        assert_notation(&pos, &dice, "24/20 13/8");
+        assert_notation(&pos, &dice, "24/15");
+        assert_notation(&pos, &dice, "13/4");
+        assert_notation(&pos, &Dice::new(6, 6), "24/18(2) 13/7(2)");
+
+        let steps = parse_move(&pos, &dice, "20/15 24/20").unwrap();
+        assert!(steps == parse_move(&pos, &dice, "24/15").unwrap());
+        assert!(parse_move(&pos, &dice, "24/18").is_none());
+        assert!(parse_move(&pos, &dice, "13/8 13").is_none());
+    }
+
+    #[test]
+    fn every_move_round_trips() {
+        let pos = Backgammon::new();
+        for dice in [
+            Dice::new(5, 4),
+            Dice::new(3, 1),
+            Dice::new(6, 6),
+            Dice::new(2, 2),
+        ] {
+            for result in pos.possible_positions(&dice) {
+                let notation = format_move(&pos, &dice, &result).unwrap();
+                assert!(parse_move(&pos, &dice, &notation).unwrap() == result);
+            }
+        }
+    }
+
+    #[test]
+    fn hits() {
+        let pos = bpos!(x 13:2; o 9:1);
+        let dice = Dice::new(5, 4);
+        assert_notation(&pos, &dice, "13/9*/4");
+        assert_notation(&pos, &dice, "13/9* 13/8");
+        assert_notation(&pos, &dice, "13/4");
+    }
+
+    #[test]
+    fn bar_and_off() {
+        // Entering with the 6 is blocked and the checker on 3 can't bear off yet.
+        let pos = bpos!(x 25:1, 3:1; o 19:2);
+        assert_notation(&pos, &Dice::new(6, 3), "bar/16");
+
+        let pos = bpos!(x 2:2; o 24:1);
+        assert_notation(&pos, &Dice::new(6, 5), "2/off(2)");
+    }
+}
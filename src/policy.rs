@@ -0,0 +1,200 @@
+use crate::evaluator::PartialEvaluator;
+use bkgm::{Dice, State};
+use std::marker::PhantomData;
+
+/// Strategy for choosing a move among the positions returned by `possible_positions`.
+pub trait Policy<G: State> {
+    /// Returns the index of the chosen position. `positions` are resulting positions, so they are
+    /// seen from the opponent. Must not be called with an empty slice.
+    fn choose(&mut self, positions: &[G]) -> usize;
+
+    fn choose_position(&mut self, pos: &G, dice: &Dice) -> G {
+        let positions = pos.possible_positions(dice);
+        positions[self.choose(&positions)]
+    }
+}
+
+/// Always plays the best move, like `best_position`.
+pub struct Greedy<E: PartialEvaluator<G>, G: State> {
+    evaluator: E,
+    phantom: PhantomData<G>,
+}
+
+impl<E: PartialEvaluator<G>, G: State> Greedy<E, G> {
+    pub fn new(evaluator: E) -> Self {
+        Self {
+            evaluator,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E: PartialEvaluator<G>, G: State> Policy<G> for Greedy<E, G> {
+    fn choose(&mut self, positions: &[G]) -> usize {
+        best_index(&self.evaluator.try_eval_batch(positions))
+    }
+}
+
+/// Chooses moves with a probability proportional to `exp(equity / temperature)`.
+/// The lower the temperature, the more often the best move is played. A temperature of 0 is greedy.
+pub struct Softmax<E: PartialEvaluator<G>, G: State> {
+    evaluator: E,
+    temperature: f32,
+    rng: fastrand::Rng,
+    phantom: PhantomData<G>,
+}
+
+impl<E: PartialEvaluator<G>, G: State> Softmax<E, G> {
+    /// Samples moves weighted by the equities of `evaluator` at the given `temperature`, in units
+    /// of equity. The sampling is seeded randomly, see `with_seed` for repeatable runs.
+    pub fn new(evaluator: E, temperature: f32) -> Self {
+        Self::with_rng(evaluator, temperature, fastrand::Rng::new())
+    }
+
+    pub fn with_seed(evaluator: E, temperature: f32, seed: u64) -> Self {
+        Self::with_rng(evaluator, temperature, fastrand::Rng::with_seed(seed))
+    }
+
+    fn with_rng(evaluator: E, temperature: f32, rng: fastrand::Rng) -> Self {
+        Self {
+            evaluator,
+            temperature,
+            rng,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+}
+
+impl<E: PartialEvaluator<G>, G: State> Policy<G> for Softmax<E, G> {
+    fn choose(&mut self, positions: &[G]) -> usize {
+        let values = self.evaluator.try_eval_batch(positions);
+        if self.temperature <= 0.0 {
+            return best_index(&values);
+        }
+        // Equities of the player on roll are the negated values. Shift by the best one, so
+        // that the exponentials can't overflow.
+        let best = values[best_index(&values)];
+        let weights: Vec<f32> = values
+            .iter()
+            .map(|value| ((best - value) / self.temperature).exp())
+            .collect();
+        let mut remaining = self.rng.f32() * weights.iter().sum::<f32>();
+        for (i, weight) in weights.iter().enumerate() {
+            if remaining < *weight {
+                return i;
+            }
+            remaining -= weight;
+        }
+        // Only reached through rounding errors
+        weights.len() - 1
+    }
+}
+
+/// Plays a random move with probability `epsilon` and the best move otherwise.
+pub struct EpsilonGreedy<E: PartialEvaluator<G>, G: State> {
+    evaluator: E,
+    epsilon: f32,
+    rng: fastrand::Rng,
+    phantom: PhantomData<G>,
+}
+
+impl<E: PartialEvaluator<G>, G: State> EpsilonGreedy<E, G> {
+    /// Plays a uniformly random move in a share `epsilon` of the decisions, between 0 and 1, and
+    /// the best move of `evaluator` in all others. Seeded randomly, see `with_seed`.
+    pub fn new(evaluator: E, epsilon: f32) -> Self {
+        Self::with_rng(evaluator, epsilon, fastrand::Rng::new())
+    }
+
+    pub fn with_seed(evaluator: E, epsilon: f32, seed: u64) -> Self {
+        Self::with_rng(evaluator, epsilon, fastrand::Rng::with_seed(seed))
+    }
+
+    fn with_rng(evaluator: E, epsilon: f32, rng: fastrand::Rng) -> Self {
+        Self {
+            evaluator,
+            epsilon,
+            rng,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn epsilon(&self) -> f32 {
+        self.epsilon
+    }
+}
+
+impl<E: PartialEvaluator<G>, G: State> Policy<G> for EpsilonGreedy<E, G> {
+    fn choose(&mut self, positions: &[G]) -> usize {
+        if self.rng.f32() < self.epsilon {
+            self.rng.usize(0..positions.len())
+        } else {
+            best_index(&self.evaluator.try_eval_batch(positions))
+        }
+    }
+}
+
+/// Index of the lowest value, which is the best move for the player on roll.
+fn best_index(values: &[f32]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+        .unwrap()
+        .0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{PartialEvaluator, PubEval};
+    use crate::policy::{EpsilonGreedy, Greedy, Policy, Softmax};
+    use bkgm::{Backgammon, Dice, State};
+    use std::collections::HashSet;
+
+    fn choices<P: Policy<Backgammon>>(policy: &mut P, positions: &[Backgammon]) -> Vec<usize> {
+        (0..100).map(|_| policy.choose(positions)).collect()
+    }
+
+    #[test]
+    fn greedy_plays_best_position() {
+        let pos = Backgammon::new();
+        let dice = Dice::new(5, 4);
+        let mut greedy = Greedy::new(PubEval::new());
+        let best = PubEval::new().best_position(&pos, &dice);
+        assert!(greedy.choose_position(&pos, &dice) == best);
+
+        let positions = pos.possible_positions(&dice);
+        let best = greedy.choose(&positions);
+        let mut cold = Softmax::new(PubEval::new(), 0.0);
+        let mut never = EpsilonGreedy::new(PubEval::new(), 0.0);
+        assert!(choices(&mut cold, &positions).iter().all(|&i| i == best));
+        assert!(choices(&mut never, &positions).iter().all(|&i| i == best));
+    }
+
+    #[test]
+    fn softmax_is_deterministic_by_seed() {
+        let positions = Backgammon::new().possible_positions(&Dice::new(3, 1));
+        let mut first = Softmax::with_seed(PubEval::new(), 0.1, 42);
+        let mut second = Softmax::with_seed(PubEval::new(), 0.1, 42);
+        assert_eq!(
+            choices(&mut first, &positions),
+            choices(&mut second, &positions)
+        );
+    }
+
+    #[test]
+    fn exploration_chooses_several_moves() {
+        let positions = Backgammon::new().possible_positions(&Dice::new(5, 4));
+        let mut hot = Softmax::with_seed(PubEval::new(), 100.0, 1);
+        let mut always = EpsilonGreedy::with_seed(PubEval::new(), 1.0, 1);
+        for choices in [
+            choices(&mut hot, &positions),
+            choices(&mut always, &positions),
+        ] {
+            assert!(choices.iter().collect::<HashSet<_>>().len() > 1);
+        }
+    }
+}
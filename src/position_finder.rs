@@ -1,5 +1,6 @@
 use crate::dice::{DiceGen, FastrandDice};
-use crate::evaluator::Evaluator;
+use crate::evaluator::PartialEvaluator;
+use crate::policy::{Greedy, Policy};
 use bkgm::GameState::Ongoing;
 use bkgm::State;
 use std::collections::HashSet;
@@ -7,18 +8,25 @@ use std::hash::Hash;
 use std::marker::PhantomData;
 
 /// Finds random positions for later rollout.
-pub struct PositionFinder<E: Evaluator<G>, G: State> {
-    evaluator: E,
+pub struct PositionFinder<P: Policy<G>, G: State> {
+    policy: P,
     dice_gen: FastrandDice,
     phantom: PhantomData<G>,
 }
 
-impl<E: Evaluator<G>, G: State + Eq + Hash> PositionFinder<E, G> {
+impl<E: PartialEvaluator<G>, G: State + Eq + Hash> PositionFinder<Greedy<E, G>, G> {
     /// Contains different random number generators every time it's called.
     #[allow(clippy::new_without_default)]
     pub fn new(evaluator: E) -> Self {
+        Self::with_policy(Greedy::new(evaluator))
+    }
+}
+
+impl<P: Policy<G>, G: State + Eq + Hash> PositionFinder<P, G> {
+    /// Plays the games with the given policy, e.g. `Softmax` for more diverse positions.
+    pub fn with_policy(policy: P) -> Self {
         PositionFinder {
-            evaluator,
+            policy,
             dice_gen: FastrandDice::new(),
             phantom: PhantomData,
         }
@@ -44,10 +52,8 @@ impl<E: Evaluator<G>, G: State + Eq + Hash> PositionFinder<E, G> {
         while pos.game_state() == Ongoing {
             // Todo: Don't allow doubles in first move
             let dice = self.dice_gen.roll();
-            let pos = self.evaluator.best_position(&pos, &dice);
             let new_positions = pos.possible_positions(&dice);
-            // Todo: remove cloning by implementing the Copy trait -> maybe better performance
-            // pos = self.evaluator.worst_position(&new_positions).clone();
+            pos = new_positions[self.policy.choose(&new_positions)];
             let mut ongoing_games: Vec<G> = new_positions
                 .into_iter()
                 .filter(|p| p.game_state() == Ongoing)
@@ -57,3 +63,21 @@ impl<E: Evaluator<G>, G: State + Eq + Hash> PositionFinder<E, G> {
         positions
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::PubEval;
+    use crate::policy::Softmax;
+    use crate::position_finder::PositionFinder;
+    use bkgm::Backgammon;
+
+    #[test]
+    fn finds_requested_amount() {
+        let mut finder = PositionFinder::<_, Backgammon>::new(PubEval::new());
+        assert_eq!(finder.find_positions(50).len(), 50);
+
+        let mut finder =
+            PositionFinder::<_, Backgammon>::with_policy(Softmax::new(PubEval::new(), 0.2));
+        assert_eq!(finder.find_positions(50).len(), 50);
+    }
+}
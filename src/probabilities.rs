@@ -55,6 +55,26 @@ impl Probabilities {
         }
     }
 
+    /// Weighted average, e.g. over the 21 distinct rolls weighted by how often they occur.
+    pub fn weighted_average(values: &[(Probabilities, f32)]) -> Self {
+        let mut sum = [0.0; 6];
+        let mut total = 0.0;
+        for (probs, weight) in values {
+            for (s, p) in sum.iter_mut().zip(probs.to_slice()) {
+                *s += weight * p;
+            }
+            total += weight;
+        }
+        Probabilities {
+            win_normal: sum[0] / total,
+            win_gammon: sum[1] / total,
+            win_bg: sum[2] / total,
+            lose_normal: sum[3] / total,
+            lose_gammon: sum[4] / total,
+            lose_bg: sum[5] / total,
+        }
+    }
+
     pub fn win_prob(&self) -> f32 {
         self.win_normal + self.win_gammon + self.win_bg
     }
@@ -221,7 +241,7 @@ impl From<&[f32; 5]> for Probabilities {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct ResultCounter {
     results: [u32; 6],
 }
@@ -263,6 +283,19 @@ impl ResultCounter {
         self.results[result as usize]
     }
 
+    /// Counts from the view of the opponent.
+    pub fn flip(&self) -> Self {
+        let [win_normal, win_gammon, win_bg, lose_normal, lose_gammon, lose_bg] = self.results;
+        Self::new(
+            lose_normal,
+            lose_gammon,
+            lose_bg,
+            win_normal,
+            win_gammon,
+            win_bg,
+        )
+    }
+
     pub fn combine(self, counter: &ResultCounter) -> Self {
         let mut results = self.results;
         for (self_value, counter_value) in results.iter_mut().zip(counter.results) {
@@ -275,6 +308,7 @@ impl ResultCounter {
 #[cfg(test)]
 mod probabilities_tests {
     use crate::probabilities::Probabilities;
+    use bkgm::GameResult;
 
     #[test]
     fn new() {
@@ -289,6 +323,16 @@ mod probabilities_tests {
         assert_eq!(probabilities.lose_bg, 0.5);
     }
 
+    #[test]
+    fn weighted_average() {
+        let win = Probabilities::from_result(&GameResult::WinNormal);
+        let lose = Probabilities::from_result(&GameResult::LoseGammon);
+        let average = Probabilities::weighted_average(&[(win, 3.0), (lose, 1.0)]);
+        assert_eq!(average.win_normal, 0.75);
+        assert_eq!(average.lose_gammon, 0.25);
+        assert_eq!(average.equity(), 0.25);
+    }
+
     #[test]
     fn equity_win_normal() {
         let probabilities = Probabilities {
@@ -0,0 +1,236 @@
+use crate::evaluator::{Budget, Evaluator, MoveFilter, PlyEvaluator};
+use crate::probabilities::Probabilities;
+use bkgm::{Dice, State};
+use std::time::{Duration, Instant};
+
+/// Limits of an `IterativeDeepening` search.
+///
+/// The search stops at `max_depth`, or earlier once the time or the number of evaluations is used
+/// up. Unfinished iterations are discarded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchBudget {
+    pub time: Option<Duration>,
+    /// Positions evaluated by the inner evaluator, summed over all iterations.
+    pub evaluations: Option<u64>,
+    pub max_depth: usize,
+}
+
+impl Default for SearchBudget {
+    /// No time or evaluation limit, up to 2-ply.
+    fn default() -> Self {
+        Self {
+            time: None,
+            evaluations: None,
+            max_depth: 2,
+        }
+    }
+}
+
+impl SearchBudget {
+    pub fn with_time(mut self, time: Duration) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn with_evaluations(mut self, evaluations: u64) -> Self {
+        self.evaluations = Some(evaluations);
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+}
+
+/// How a search went.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchStats {
+    /// Depth of the deepest completed iteration.
+    pub depth: usize,
+    /// Positions evaluated by the inner evaluator, including those of an aborted iteration.
+    pub evaluations: u64,
+    pub elapsed: Duration,
+    /// Candidates searched in the deepest completed iteration.
+    pub candidates: usize,
+    /// Whether the budget ran out before `max_depth` was reached.
+    pub aborted: bool,
+}
+
+/// Best move found by a search.
+#[derive(Clone, Debug)]
+pub struct SearchResult<G: State> {
+    /// Resulting position as returned by `possible_positions`, seen from the opponent.
+    pub position: G,
+    /// Probabilities from the view of the player making the move.
+    pub probabilities: Probabilities,
+    pub stats: SearchStats,
+}
+
+impl<G: State> SearchResult<G> {
+    /// Cubeless equity from the view of the player making the move.
+    pub fn equity(&self) -> f32 {
+        self.probabilities.equity()
+    }
+}
+
+/// Searches the moves of a roll 0-ply, 1-ply, 2-ply and so on, until the `SearchBudget` is used
+/// up, and plays the best move of the deepest completed iteration. Answers within a deadline,
+/// e.g. for interactive play or a server.
+pub struct IterativeDeepening<E: Evaluator<G>, G: State> {
+    ply: PlyEvaluator<E, G>,
+    budget: SearchBudget,
+    filter: Option<MoveFilter>,
+}
+
+impl<E: Evaluator<G>, G: State> IterativeDeepening<E, G> {
+    pub fn new(evaluator: E, budget: SearchBudget) -> Self {
+        Self {
+            ply: PlyEvaluator::new(evaluator, 0),
+            budget,
+            filter: None,
+        }
+    }
+
+    /// Each iteration only searches the candidates surviving `filter` in the previous one, and
+    /// the searches below them are filtered like in `PlyEvaluator::with_filter`.
+    pub fn with_filter(mut self, filter: MoveFilter) -> Self {
+        self.ply = self.ply.with_filter(filter);
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn budget(&self) -> &SearchBudget {
+        &self.budget
+    }
+
+    /// The 0-ply iteration always completes, so there is a move even if the budget is tiny.
+    pub fn search(&self, pos: &G, dice: &Dice) -> SearchResult<G> {
+        let start = Instant::now();
+        let mut budget = Budget::new(
+            self.budget.time.map(|time| start + time),
+            self.budget.evaluations,
+        );
+        let mut candidates = pos.possible_positions(dice);
+        let mut best = None;
+        let mut stats = SearchStats {
+            depth: 0,
+            evaluations: 0,
+            elapsed: Duration::ZERO,
+            candidates: candidates.len(),
+            aborted: false,
+        };
+
+        for depth in 0..=self.budget.max_depth {
+            let evaluated = if depth == 0 {
+                let mut unlimited = Budget::unlimited();
+                let evaluated = self.ply.bounded_ply_batch(&candidates, 0, &mut unlimited);
+                budget.record(unlimited.evaluations());
+                evaluated
+            } else {
+                self.ply.bounded_ply_batch(&candidates, depth, &mut budget)
+            };
+            let evaluated = match evaluated {
+                Some(evaluated) => evaluated,
+                None => {
+                    stats.aborted = true;
+                    break;
+                }
+            };
+
+            let values: Vec<f32> = evaluated.iter().map(|probs| probs.equity()).collect();
+            let ranked = match self.filter {
+                Some(filter) => filter.survivors(&values),
+                None => {
+                    let mut ranked: Vec<usize> = (0..values.len()).collect();
+                    ranked.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+                    ranked
+                }
+            };
+            best = Some((candidates[ranked[0]], evaluated[ranked[0]].flip()));
+            stats.depth = depth;
+            stats.candidates = candidates.len();
+
+            candidates = ranked.into_iter().map(|i| candidates[i]).collect();
+            if candidates.len() == 1 {
+                // Nothing left to decide
+                break;
+            }
+        }
+
+        let (position, probabilities) = best.unwrap();
+        stats.evaluations = budget.evaluations();
+        stats.elapsed = start.elapsed();
+        SearchResult {
+            position,
+            probabilities,
+            stats,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{Evaluator, OffEvaluator, PartialEvaluator, PlyEvaluator};
+    use crate::search::{IterativeDeepening, SearchBudget};
+    use crate::test_utils::race_position;
+    use bkgm::GameState::GameOver;
+    use bkgm::{bpos, Dice, State};
+    use std::time::Duration;
+
+    #[test]
+    fn reaches_max_depth() {
+        let budget = SearchBudget::default().with_max_depth(1);
+        let search = IterativeDeepening::new(OffEvaluator, budget);
+        let pos = race_position();
+        let dice = Dice::new(6, 5);
+
+        let result = search.search(&pos, &dice);
+        assert_eq!(result.stats.depth, 1);
+        assert!(!result.stats.aborted);
+        assert_eq!(
+            result.position,
+            PlyEvaluator::new(OffEvaluator, 1).best_position(&pos, &dice)
+        );
+        let expected = PlyEvaluator::new(OffEvaluator, 1).eval(&result.position);
+        assert!((result.equity() + expected.equity()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn evaluation_budget_aborts() {
+        let budget = SearchBudget::default()
+            .with_max_depth(3)
+            .with_evaluations(20);
+        let search = IterativeDeepening::new(OffEvaluator, budget);
+
+        // A 1-ply search evaluates the replies to 21 rolls for each of the candidates.
+        let result = search.search(&race_position(), &Dice::new(6, 5));
+        assert!(result.stats.aborted);
+        assert_eq!(result.stats.depth, 0);
+        assert!(result.stats.evaluations <= 20);
+    }
+
+    #[test]
+    fn zero_time_plays_zero_ply() {
+        let budget = SearchBudget::default().with_time(Duration::ZERO);
+        let search = IterativeDeepening::new(OffEvaluator, budget);
+        let pos = race_position();
+        let dice = Dice::new(6, 5);
+
+        let result = search.search(&pos, &dice);
+        assert_eq!(result.stats.depth, 0);
+        assert!(result.stats.aborted);
+        assert_eq!(result.position, OffEvaluator.best_position(&pos, &dice));
+    }
+
+    #[test]
+    fn finds_winning_move() {
+        let budget = SearchBudget::default();
+        let search = IterativeDeepening::new(OffEvaluator, budget);
+        let pos = bpos!(x 6:1, 1:1; o 24:2);
+
+        let result = search.search(&pos, &Dice::new(6, 1));
+        assert!(matches!(result.position.game_state(), GameOver(_)));
+        assert_eq!(result.equity(), 1.0);
+    }
+}
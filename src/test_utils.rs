@@ -0,0 +1,15 @@
+use bkgm::{bpos, Backgammon};
+
+pub(crate) fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 1e-5,
+        "{} is not close to {}",
+        actual,
+        expected
+    );
+}
+
+/// Bear-off race whose moves and rolls `OffEvaluator` tells apart.
+pub(crate) fn race_position() -> Backgammon {
+    bpos!(x 6:2, 5:2, 4:1; o 19:2, 20:3)
+}